@@ -1,6 +1,6 @@
 //! ECS input bundle
 
-use crate::{BindingError, BindingTypes, Bindings, InputSystemDesc};
+use crate::{BindingError, BindingTypes, Bindings, InputHistorySystemDesc, InputSystemDesc};
 use amethyst_config::{Config, ConfigError};
 use amethyst_core::{
     ecs::prelude::{DispatcherBuilder, World},
@@ -12,6 +12,8 @@ use std::{error, fmt, path::Path};
 
 #[cfg(feature = "sdl_controller")]
 use crate::sdl_events_system::ControllerMappings;
+#[cfg(feature = "gilrs_controller")]
+use crate::GilrsConfig;
 
 /// Bundle for adding the `InputHandler`.
 ///
@@ -32,8 +34,11 @@ use crate::sdl_events_system::ControllerMappings;
 #[derivative(Default(bound = ""))]
 pub struct InputBundle<T: BindingTypes> {
     bindings: Option<Bindings<T>>,
+    input_history_capacity: Option<usize>,
     #[cfg(feature = "sdl_controller")]
     controller_mappings: Option<ControllerMappings>,
+    #[cfg(feature = "gilrs_controller")]
+    gilrs_config: GilrsConfig,
 }
 
 impl<T: BindingTypes> InputBundle<T> {
@@ -61,6 +66,12 @@ impl<T: BindingTypes> InputBundle<T> {
         Ok(self.with_bindings(bindings))
     }
 
+    /// Record the last `capacity` input events in the `InputHistory` resource
+    pub fn with_input_history(mut self, capacity: usize) -> Self {
+        self.input_history_capacity = Some(capacity);
+        self
+    }
+
     /// Load SDL controller mappings from file
     #[cfg(feature = "sdl_controller")]
     pub fn with_sdl_controller_mappings(mut self, mappings: String) -> Self {
@@ -77,6 +88,18 @@ impl<T: BindingTypes> InputBundle<T> {
         self.controller_mappings = Some(ControllerMappings::FromPath(path_buf));
         self
     }
+
+    /// Use the provided configuration for the gilrs controller system
+    ///
+    /// With `GilrsConfig::order_with_window_events` set, the `InputSystem` and the
+    /// `InputHistorySystem` are added as thread local systems running after the gilrs system
+    /// instead of parallel ones, so they can't be named as dependencies of other systems. Add the
+    /// window bundle first for the window events to be polled before them.
+    #[cfg(feature = "gilrs_controller")]
+    pub fn with_gilrs_config(mut self, config: GilrsConfig) -> Self {
+        self.gilrs_config = config;
+        self
+    }
 }
 
 impl<'a, 'b, T: BindingTypes> SystemBundle<'a, 'b> for InputBundle<T> {
@@ -93,16 +116,34 @@ impl<'a, 'b, T: BindingTypes> SystemBundle<'a, 'b> for InputBundle<T> {
                 SdlEventsSystem::<T>::new(world, self.controller_mappings).unwrap(),
             );
         }
+        // Controller events queued for ordering are pumped by a thread local system, which runs
+        // after the parallel ones: publish them in the same frame by running after it.
+        #[cfg(feature = "gilrs_controller")]
+        let thread_local_input = self.gilrs_config.order_with_window_events;
+        #[cfg(not(feature = "gilrs_controller"))]
+        let thread_local_input = false;
         #[cfg(feature = "gilrs_controller")]
         {
             use super::GilrsEventsSystem;
-            builder.add_thread_local(GilrsEventsSystem::<T>::new(world).unwrap());
+            builder.add_thread_local(
+                GilrsEventsSystem::<T>::with_config(world, self.gilrs_config).unwrap(),
+            );
+        }
+        let input_system = InputSystemDesc::<T>::new(self.bindings).build(world);
+        let history_system = self
+            .input_history_capacity
+            .map(|capacity| InputHistorySystemDesc::<T>::new(capacity).build(world));
+        if thread_local_input {
+            builder.add_thread_local(input_system);
+            if let Some(history_system) = history_system {
+                builder.add_thread_local(history_system);
+            }
+        } else {
+            builder.add(input_system, "input_system", &[]);
+            if let Some(history_system) = history_system {
+                builder.add(history_system, "input_history_system", &["input_system"]);
+            }
         }
-        builder.add(
-            InputSystemDesc::<T>::new(self.bindings).build(world),
-            "input_system",
-            &[],
-        );
         Ok(())
     }
 }
@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use crate::{bindings::BindingTypes, event::InputEvent};
 
 /// Controller axes matching SDL controller model
-#[derive(Eq, PartialEq, Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Hash, Serialize, Deserialize)]
 pub enum ControllerAxis {
     /// The X axis on the left stick
     LeftX,
@@ -21,6 +21,19 @@ pub enum ControllerAxis {
     Unknown,
 }
 
+impl ControllerAxis {
+    /// All axes, in the order they are numbered in controller state snapshots.
+    pub(crate) const ALL: [ControllerAxis; 7] = [
+        ControllerAxis::LeftX,
+        ControllerAxis::LeftY,
+        ControllerAxis::RightX,
+        ControllerAxis::RightY,
+        ControllerAxis::LeftTrigger,
+        ControllerAxis::RightTrigger,
+        ControllerAxis::Unknown,
+    ];
+}
+
 /// Controller buttons matching SDL controller model
 #[derive(Eq, PartialEq, Debug, Copy, Clone, Hash, Serialize, Deserialize)]
 pub enum ControllerButton {
@@ -66,6 +79,55 @@ pub enum ControllerButton {
     Unknown,
 }
 
+impl ControllerButton {
+    /// All buttons, in the order they are numbered in controller state snapshots.
+    pub(crate) const ALL: [ControllerButton; 18] = [
+        ControllerButton::A,
+        ControllerButton::B,
+        ControllerButton::X,
+        ControllerButton::Y,
+        ControllerButton::DPadDown,
+        ControllerButton::DPadLeft,
+        ControllerButton::DPadRight,
+        ControllerButton::DPadUp,
+        ControllerButton::LeftShoulder,
+        ControllerButton::LeftTrigger,
+        ControllerButton::RightShoulder,
+        ControllerButton::RightTrigger,
+        ControllerButton::LeftStick,
+        ControllerButton::RightStick,
+        ControllerButton::Back,
+        ControllerButton::Start,
+        ControllerButton::Guide,
+        ControllerButton::Unknown,
+    ];
+}
+
+/// Points at which an analog trigger also counts as its digital button, see
+/// `InputHandler::set_trigger_threshold`.
+///
+/// The button is pressed once the trigger axis reaches `press` and released once it falls back to
+/// `release`, so keeping `release` below `press` stops a trigger resting near the threshold from
+/// toggling the button on every small movement.
+#[derive(PartialEq, Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct TriggerThreshold {
+    /// Axis value from which the button is pressed.
+    pub press: f32,
+    /// Axis value from which the button is released.
+    pub release: f32,
+}
+
+impl ControllerAxis {
+    /// Returns the digital button of a trigger axis.
+    pub(crate) fn trigger_button(self) -> Option<ControllerButton> {
+        match self {
+            ControllerAxis::LeftTrigger => Some(ControllerButton::LeftTrigger),
+            ControllerAxis::RightTrigger => Some(ControllerButton::RightTrigger),
+            _ => None,
+        }
+    }
+}
+
 /// Controller events generated by the SDL events system.
 #[derive(PartialEq, Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum ControllerEvent {
@@ -124,6 +186,24 @@ pub enum ControllerEvent {
         /// the `SDL_CONTROLLERDEVICEREMOVED` or `SDL_CONTROLLERDEVICEREMAPPED` event
         which: u32,
     },
+    /// A connected controller looks like the same physical device as an already connected one,
+    /// e.g. because it is surfaced by two different backends.
+    ControllerDuplicateDetected {
+        /// The id of the duplicate controller.
+        which: u32,
+        /// The id of the controller it duplicates.
+        duplicate_of: u32,
+    },
+    /// A controller had no input for the configured inactivity period.
+    ControllerIdle {
+        /// The id of the idle controller.
+        which: u32,
+    },
+    /// An idle controller received input again.
+    ControllerActive {
+        /// The id of the controller.
+        which: u32,
+    },
 }
 
 impl<'a, T> Into<InputEvent<T>> for &'a ControllerEvent
@@ -133,9 +213,12 @@ where
     fn into(self) -> InputEvent<T> {
         use self::ControllerEvent::*;
         match *self {
-            ControllerAxisMoved { which, axis, value } => {
-                InputEvent::ControllerAxisMoved { which, axis, value }
-            }
+            ControllerAxisMoved { which, axis, value } => InputEvent::ControllerAxisMoved {
+                which,
+                axis,
+                value,
+                raw: value,
+            },
             ControllerButtonPressed { which, button } => {
                 InputEvent::ControllerButtonPressed { which, button }
             }
@@ -144,6 +227,15 @@ where
             }
             ControllerConnected { which } => InputEvent::ControllerConnected { which },
             ControllerDisconnected { which } => InputEvent::ControllerDisconnected { which },
+            ControllerDuplicateDetected {
+                which,
+                duplicate_of,
+            } => InputEvent::ControllerDuplicateDetected {
+                which,
+                duplicate_of,
+            },
+            ControllerIdle { which } => InputEvent::ControllerIdle { which },
+            ControllerActive { which } => InputEvent::ControllerActive { which },
         }
     }
 }
@@ -77,8 +77,11 @@ where
         which: u32,
         /// The axis that moved on the controller.
         axis: ControllerAxis,
-        /// The amount that the axis moved.
+        /// The new axis value, with the dead zone set through
+        /// `InputHandler::set_controller_axis_dead_zone` applied.
         value: f32,
+        /// The new axis value as reported by the controller.
+        raw: f32,
     },
     ///  A controller button was pressed.
     ControllerButtonPressed {
@@ -104,6 +107,23 @@ where
         /// The id for the controller disconnected.
         which: u32,
     },
+    /// Connected controller looks like the same device as an already connected controller.
+    ControllerDuplicateDetected {
+        /// The id for the duplicate controller.
+        which: u32,
+        /// The id for the controller it duplicates.
+        duplicate_of: u32,
+    },
+    /// Controller had no input for the configured inactivity period.
+    ControllerIdle {
+        /// The id for the idle controller.
+        which: u32,
+    },
+    /// Idle controller received input again.
+    ControllerActive {
+        /// The id for the controller.
+        which: u32,
+    },
     /// The associated action had any related button or combination pressed.
     ///
     /// If a combination is bound to an action, it will be pressed
@@ -0,0 +1,117 @@
+//! Force feedback requests sent to controllers.
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Vibration of a controller's motors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rumble {
+    /// Intensity of the strong, low frequency motor, from 0.0 to 1.0.
+    pub strong: f32,
+    /// Intensity of the weak, high frequency motor, from 0.0 to 1.0.
+    pub weak: f32,
+    /// How long the controller vibrates.
+    pub duration: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RumbleRequest {
+    rumble: Rumble,
+    requested_at: Instant,
+    revision: u64,
+}
+
+/// Rumble requests for the connected controllers, keyed by the controller id used in
+/// `ControllerEvent`.
+///
+/// The `GilrsEventsSystem` starts the rumble of a controller when it is requested, replaces it
+/// when a new request for the same controller arrives and stops it when the request is cleared.
+/// Requests are removed once their duration has elapsed. Controllers which don't support force
+/// feedback ignore the requests.
+#[derive(Debug, Default)]
+pub struct ControllerFeedback {
+    requests: HashMap<u32, RumbleRequest>,
+    next_revision: u64,
+}
+
+impl ControllerFeedback {
+    /// Requests the controller to vibrate, replacing its current rumble if any.
+    pub fn rumble(&mut self, which: u32, rumble: Rumble) {
+        self.requests.insert(
+            which,
+            RumbleRequest {
+                rumble,
+                requested_at: Instant::now(),
+                revision: self.next_revision,
+            },
+        );
+        self.next_revision += 1;
+    }
+
+    /// Clears the rumble request of the controller, which stops its vibration.
+    pub fn stop(&mut self, which: u32) -> Option<Rumble> {
+        self.requests.remove(&which).map(|request| request.rumble)
+    }
+
+    /// Clears the rumble requests of all controllers.
+    pub fn stop_all(&mut self) {
+        self.requests.clear();
+    }
+
+    /// Returns the rumble currently requested for the controller.
+    pub fn requested(&self, which: u32) -> Option<&Rumble> {
+        self.requests.get(&which).map(|request| &request.rumble)
+    }
+
+    /// Removes the requests whose duration has elapsed and returns the others as the controller
+    /// id, a revision changing with every new request and the rumble left to play.
+    pub(crate) fn update(&mut self, now: Instant) -> Vec<(u32, u64, Rumble)> {
+        self.requests.retain(|_, request| {
+            now.duration_since(request.requested_at) < request.rumble.duration
+        });
+        self.requests
+            .iter()
+            .map(|(&which, request)| {
+                let elapsed = now.duration_since(request.requested_at);
+                let rumble = Rumble {
+                    duration: request.rumble.duration - elapsed,
+                    ..request.rumble
+                };
+                (which, request.revision, rumble)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rumble_requests_are_replaced_and_expire() {
+        let mut feedback = ControllerFeedback::default();
+        let rumble = Rumble {
+            strong: 1.0,
+            weak: 0.5,
+            duration: Duration::from_millis(100),
+        };
+        feedback.rumble(0, rumble);
+        let now = Instant::now();
+        let requests = feedback.update(now);
+        assert_eq!(requests.len(), 1);
+        let (which, revision, remaining) = requests[0];
+        assert_eq!(which, 0);
+        assert!(remaining.duration <= rumble.duration);
+
+        // A new request for the same controller gets a new revision
+        feedback.rumble(0, rumble);
+        assert_ne!(feedback.update(now)[0].1, revision);
+        feedback.rumble(1, rumble);
+        assert_eq!(feedback.stop(1), Some(rumble));
+        assert!(feedback.requested(1).is_none());
+
+        assert!(feedback.update(Instant::now() + rumble.duration).is_empty());
+        assert!(feedback.requested(0).is_none());
+    }
+}
@@ -1,13 +1,19 @@
 use std::{
-    collections::{hash_map::DefaultHasher, HashMap},
+    collections::HashMap,
+    convert::TryFrom,
     fmt,
-    hash::{Hash, Hasher},
+    hash::Hash,
     marker::PhantomData,
+    time::{Duration, Instant, SystemTime},
 };
 
 use derivative::Derivative;
 use derive_new::new;
-use gilrs::{Axis, Button, Event, EventType, GamepadId, Gilrs};
+use gilrs::{
+    ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Repeat, Replay, Ticks},
+    Axis, Button, Event, EventType, GamepadId, Gilrs,
+};
+use smallvec::{smallvec, SmallVec};
 
 use amethyst_core::{
     ecs::prelude::{System, SystemData, World, Write},
@@ -17,7 +23,7 @@ use amethyst_core::{
 
 use super::{
     controller::{ControllerAxis, ControllerButton, ControllerEvent},
-    BindingTypes, InputEvent, InputHandler,
+    BindingTypes, ControllerFeedback, InputEvent, InputHandler, Rumble, TimestampedInputEvents,
 };
 
 /// A collection of errors that can occur in the SDL system.
@@ -42,6 +48,49 @@ impl fmt::Display for GilrsSystemError {
     }
 }
 
+/// Configuration of the `GilrsEventsSystem`.
+#[derive(Debug, Clone, Default)]
+pub struct GilrsConfig {
+    /// Minimum time between two `ControllerAxisMoved` events of the same controller axis.
+    /// Changes arriving in between are held back and only the latest value is emitted once the
+    /// interval has elapsed. `None` emits every change.
+    pub axis_emission_interval: Option<Duration>,
+    /// Queue controller events in the `TimestampedInputEvents` resource with their gilrs
+    /// timestamp instead of publishing them right away, so the `InputSystem` publishes them in
+    /// timestamp order. Window events have no timestamp and are published after them.
+    ///
+    /// The `InputBundle` then runs the `InputSystem` as a thread local system after this one, so
+    /// the queued events are published in the frame they were pumped in.
+    pub order_with_window_events: bool,
+    /// What to do when a controller connects which looks like an already connected one.
+    pub duplicate_controllers: DuplicateControllerPolicy,
+    /// Emit a `ControllerIdle` event once a controller had no input for this long, and a
+    /// `ControllerActive` event on its next input. `None` disables idle detection.
+    pub idle_after: Option<Duration>,
+    /// When this much wall clock time passed since the previous run, e.g. because the system
+    /// resumed from sleep, the backlog of gilrs events is discarded and the controllers are
+    /// reconciled with the ones actually connected instead. `None` always replays the backlog.
+    pub resume_gap: Option<Duration>,
+}
+
+/// How the `GilrsEventsSystem` handles a controller matching the uuid and name of an already
+/// connected controller. On some platforms gilrs surfaces the same device through two different
+/// APIs, which would otherwise double its input.
+///
+/// gilrs only exposes the uuid of the controller model, not an identity of the device itself such
+/// as its OS device path or serial number, so two controllers of the same model match as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Derivative)]
+#[derivative(Default)]
+pub enum DuplicateControllerPolicy {
+    /// Opens the duplicate under its own id and emits a `ControllerDuplicateDetected` event.
+    #[derivative(Default)]
+    Report,
+    /// Maps the duplicate onto the id of the controller it duplicates and drops its input, which
+    /// is already reported by the original. No event is emitted for it. As a second controller of
+    /// the same model is coalesced too, only use this when players don't share a model.
+    Coalesce,
+}
+
 /// Builds a `SdlEventsSystem`.
 #[derive(Derivative, Debug, new)]
 #[derivative(Default(bound = ""))]
@@ -49,6 +98,8 @@ pub struct GilrsEventsSystemDesc<T>
 where
     T: BindingTypes,
 {
+    #[new(default)]
+    config: GilrsConfig,
     marker: PhantomData<T>,
 }
 
@@ -59,7 +110,7 @@ where
     fn build(self, world: &mut World) -> GilrsEventsSystem<T> {
         <GilrsEventsSystem<T> as System<'_>>::SystemData::setup(world);
 
-        GilrsEventsSystem::new(world)
+        GilrsEventsSystem::with_config(world, self.config)
             .unwrap_or_else(|e| panic!("Failed to build SdlEventsSystem. Error: {}", e))
     }
 }
@@ -68,36 +119,106 @@ where
 #[allow(missing_debug_implementations)]
 pub struct GilrsEventsSystem<T: BindingTypes> {
     gilrs_handle: Gilrs,
-    opened_controllers: HashMap<GamepadId, u32>,
+    indices: ControllerIndices<GamepadId>,
+    identities: ControllerIdentities,
+    duplicate_controllers: DuplicateControllerPolicy,
+    axis_rate_limiter: Option<AxisRateLimiter>,
+    idle_tracker: Option<IdleTracker>,
+    resume_detector: Option<ResumeDetector>,
+    order_with_window_events: bool,
+    /// Revision of the rumble request each controller plays, with its effect if it supports
+    /// force feedback.
+    rumbles: HashMap<u32, (u64, Option<Effect>)>,
     marker: PhantomData<T>,
 }
 
 type GilrsEventsData<'a, T> = (
     Write<'a, InputHandler<T>>,
     Write<'a, EventChannel<InputEvent<T>>>,
+    Write<'a, TimestampedInputEvents>,
+    Write<'a, ControllerFeedback>,
 );
 
 impl<'a, T: BindingTypes> System<'a> for GilrsEventsSystem<T> {
     type SystemData = GilrsEventsData<'a, T>;
 
-    fn run(&mut self, (mut handler, mut output): Self::SystemData) {
-        while let Some(Event { id, event, time: _ }) = self.gilrs_handle.next_event() {
-            self.handle_gilrs_event(&id, &event, &mut handler, &mut output);
+    fn run(&mut self, (mut handler, mut output, mut timestamped, mut feedback): Self::SystemData) {
+        let mut events = Vec::new();
+        let resumed = match self.resume_detector {
+            Some(ref mut detector) => detector.resumed(SystemTime::now()),
+            None => false,
+        };
+        if resumed {
+            // Let gilrs catch up with the backlog without replaying it
+            while self.gilrs_handle.next_event().is_some() {}
+            let now = SystemTime::now();
+            for event in self.reconcile_controllers(&handler) {
+                events.push((now, event));
+            }
         }
+        while let Some(Event { id, event, time }) = self.gilrs_handle.next_event() {
+            for event in self.handle_gilrs_event(&id, &event) {
+                events.push((time, event));
+            }
+        }
+        if let Some(ref mut limiter) = self.axis_rate_limiter {
+            let now = SystemTime::now();
+            for (which, axis, value) in limiter.flush(Instant::now()) {
+                events.push((
+                    now,
+                    ControllerEvent::ControllerAxisMoved { which, axis, value },
+                ));
+            }
+        }
+        if let Some(ref mut tracker) = self.idle_tracker {
+            let now = Instant::now();
+            let mut tracked = Vec::with_capacity(events.len());
+            for (time, event) in events {
+                if let Some(active) = tracker.on_event(&event, now) {
+                    tracked.push((time, active));
+                }
+                tracked.push((time, event));
+            }
+            let system_now = SystemTime::now();
+            tracked.extend(tracker.poll(now).into_iter().map(|idle| (system_now, idle)));
+            events = tracked;
+        }
+
+        for (time, event) in events {
+            if self.order_with_window_events {
+                timestamped.push_controller_event(time, event);
+            } else {
+                handler.send_controller_event(&event, &mut output);
+            }
+        }
+
+        self.update_rumbles(&mut feedback);
     }
 }
 
 impl<T: BindingTypes> GilrsEventsSystem<T> {
     /// Creates a new instance of this system with the provided controller mappings.
     pub fn new(world: &mut World) -> Result<Self, GilrsSystemError> {
+        Self::with_config(world, GilrsConfig::default())
+    }
+
+    /// Creates a new instance of this system with the given configuration.
+    pub fn with_config(world: &mut World, config: GilrsConfig) -> Result<Self, GilrsSystemError> {
         let gilrs_handle: Gilrs = Gilrs::new().unwrap();
         GilrsEventsData::<T>::setup(world);
         let mut sys = GilrsEventsSystem {
             gilrs_handle,
-            opened_controllers: HashMap::new(),
+            indices: ControllerIndices::default(),
+            identities: ControllerIdentities::default(),
+            duplicate_controllers: config.duplicate_controllers,
+            axis_rate_limiter: config.axis_emission_interval.map(AxisRateLimiter::new),
+            idle_tracker: config.idle_after.map(IdleTracker::new),
+            resume_detector: config.resume_gap.map(ResumeDetector::new),
+            order_with_window_events: config.order_with_window_events,
+            rumbles: HashMap::new(),
             marker: PhantomData,
         };
-        let (mut handler, mut output) = GilrsEventsData::fetch(world);
+        let (mut handler, mut output, _, _) = GilrsEventsData::fetch(world);
         sys.initialize_controllers(&mut handler, &mut output);
         Ok(sys)
     }
@@ -106,79 +227,221 @@ impl<T: BindingTypes> GilrsEventsSystem<T> {
         &mut self,
         gamepad_id: &GamepadId,
         event_type: &EventType,
-        handler: &mut InputHandler<T>,
-        output: &mut EventChannel<InputEvent<T>>,
-    ) {
+    ) -> SmallVec<[ControllerEvent; 2]> {
         use self::ControllerEvent::*;
 
-        if let Some(idx) = self.opened_controllers.get(gamepad_id) {
+        if let Some(idx) = self.indices.get(*gamepad_id) {
+            // The input of a coalesced duplicate is already reported by its original
+            if self.indices.is_coalesced(*gamepad_id) {
+                if let EventType::Disconnected = *event_type {
+                    self.close_controller(*gamepad_id);
+                }
+                return SmallVec::new();
+            }
             match *event_type {
                 EventType::AxisChanged(axis, value, _code) => {
-                    handler.send_controller_event(
-                        &ControllerAxisMoved {
-                            which: *idx,
-                            axis: axis.into(),
-                            value: value,
-                        },
-                        output,
-                    );
+                    let axis = axis.into();
+                    let value = match self.axis_rate_limiter {
+                        Some(ref mut limiter) => limiter.filter(idx, axis, value, Instant::now()),
+                        None => Some(value),
+                    };
+                    value
+                        .map(|value| ControllerAxisMoved {
+                            which: idx,
+                            axis,
+                            value,
+                        })
+                        .into_iter()
+                        .collect()
                 }
-                EventType::ButtonReleased(button, _code) => {
-                    handler.send_controller_event(
-                        &ControllerButtonReleased {
-                            which: *idx,
-                            button: button.into(),
-                        },
-                        output,
-                    );
-                }
-                EventType::ButtonPressed(button, _code) => {
-                    handler.send_controller_event(
-                        &ControllerButtonPressed {
-                            which: *idx,
-                            button: button.into(),
-                        },
-                        output,
-                    );
-                }
-                EventType::Disconnected => {
-                    if let Some(idx) = self.close_controller(*gamepad_id) {
-                        handler
-                            .send_controller_event(&ControllerDisconnected { which: idx }, output);
-                    }
-                }
-                EventType::Connected => {
-                    if let Some(idx) = self.open_controller(*gamepad_id) {
-                        handler.send_controller_event(&ControllerConnected { which: idx }, output);
-                    }
-                }
-                _ => {}
+                EventType::ButtonReleased(button, _code) => smallvec![ControllerButtonReleased {
+                    which: idx,
+                    button: button.into(),
+                }],
+                EventType::ButtonPressed(button, _code) => smallvec![ControllerButtonPressed {
+                    which: idx,
+                    button: button.into(),
+                }],
+                EventType::Disconnected => self
+                    .close_controller(*gamepad_id)
+                    .map(|idx| ControllerDisconnected { which: idx })
+                    .into_iter()
+                    .collect(),
+                EventType::Connected => self.open_controller(*gamepad_id),
+                _ => SmallVec::new(),
             }
         } else {
             match *event_type {
-                EventType::Connected => {
-                    if let Some(idx) = self.open_controller(*gamepad_id) {
-                        handler.send_controller_event(&ControllerConnected { which: idx }, output);
-                    }
+                EventType::Connected => self.open_controller(*gamepad_id),
+                _ => SmallVec::new(),
+            }
+        }
+    }
+
+    fn open_controller(&mut self, which: GamepadId) -> SmallVec<[ControllerEvent; 2]> {
+        let (uuid, name) = match self.gilrs_handle.connected_gamepad(which) {
+            Some(gamepad) => (gamepad.uuid(), gamepad.name().to_owned()),
+            None => return SmallVec::new(),
+        };
+        let idx = self.indices.allocate(which);
+        let opened = self
+            .identities
+            .open(self.duplicate_controllers, idx, uuid, name);
+        if opened.which() != idx {
+            self.indices.coalesce(which, opened.which());
+        }
+        opened.events()
+    }
+
+    fn close_controller(&mut self, which: GamepadId) -> Option<u32> {
+        let idx = self.indices.release(which)?;
+        // A coalesced duplicate is still connected under the same id
+        if self.indices.is_in_use(idx) {
+            return None;
+        }
+        self.identities.remove(idx);
+        if let Some(limiter) = self.axis_rate_limiter.as_mut() {
+            limiter.forget(idx);
+        }
+        self.rumbles.remove(&idx);
+        Some(idx)
+    }
+
+    /// Closes the controllers which are gone and opens the new ones, then brings the buttons and
+    /// axes of the remaining ones in line with their current gilrs state.
+    fn reconcile_controllers(&mut self, handler: &InputHandler<T>) -> Vec<ControllerEvent> {
+        let connected = self
+            .gilrs_handle
+            .gamepads()
+            .map(|(id, _gamepad)| id)
+            .collect::<Vec<_>>();
+        let (gone, new) = self.indices.reconcile(&connected);
+        let mut events = Vec::new();
+        for id in gone {
+            if let Some(which) = self.close_controller(id) {
+                events.push(ControllerEvent::ControllerDisconnected { which });
+            }
+        }
+        for id in connected {
+            if new.contains(&id) {
+                events.extend(self.open_controller(id));
+            } else if let Some(which) = self.indices.get(id) {
+                // Coalesced duplicates share the state of their original
+                if self.indices.key_of(which) == Some(id) {
+                    events.extend(self.sync_controller_state(id, which, handler));
                 }
-                _ => {}
             }
         }
+        events
     }
 
-    fn open_controller(&mut self, which: GamepadId) -> Option<u32> {
-        match self.gilrs_handle.connected_gamepad(which) {
-            Some(_) => {
-                let idx = self.my_hash(which) as u32;
-                self.opened_controllers.insert(which, idx);
-                Some(idx)
+    /// Returns the events bringing the state of the controller in the input handler in line with
+    /// its current gilrs state.
+    fn sync_controller_state(
+        &mut self,
+        id: GamepadId,
+        which: u32,
+        handler: &InputHandler<T>,
+    ) -> Vec<ControllerEvent> {
+        use self::ControllerEvent::*;
+
+        let gamepad = match self.gilrs_handle.connected_gamepad(id) {
+            Some(gamepad) => gamepad,
+            None => return Vec::new(),
+        };
+        let controller_id = handler.controller_idx_to_id(which);
+        let mut events = Vec::new();
+        for &button in SYNCED_BUTTONS.iter() {
+            let pressed = gamepad.is_pressed(button);
+            let down = match controller_id {
+                Some(controller_id) => {
+                    handler.controller_button_is_down(controller_id, button.into())
+                }
+                None => false,
+            };
+            if pressed && !down {
+                events.push(ControllerButtonPressed {
+                    which,
+                    button: button.into(),
+                });
+            } else if !pressed && down {
+                events.push(ControllerButtonReleased {
+                    which,
+                    button: button.into(),
+                });
             }
-            None => None,
         }
+        for &axis in SYNCED_AXES.iter() {
+            events.push(ControllerAxisMoved {
+                which,
+                axis: axis.into(),
+                value: gamepad.value(axis),
+            });
+        }
+        if let Some(limiter) = self.axis_rate_limiter.as_mut() {
+            limiter.forget(which);
+        }
+        events
     }
 
-    fn close_controller(&mut self, which: GamepadId) -> Option<u32> {
-        self.opened_controllers.remove(&which)
+    /// Starts, replaces and stops the rumble of the controllers to match the requests.
+    fn update_rumbles(&mut self, feedback: &mut ControllerFeedback) {
+        let requests = feedback.update(Instant::now());
+        self.rumbles.retain(|which, (revision, effect)| {
+            let requested = requests
+                .iter()
+                .any(|&(w, r, _)| w == *which && r == *revision);
+            if !requested {
+                if let Some(effect) = effect {
+                    // The effect also stops when dropped, errors mean it is gone already
+                    let _ = effect.stop();
+                }
+            }
+            requested
+        });
+        for (which, revision, rumble) in requests {
+            if !self.rumbles.contains_key(&which) {
+                let effect = self.start_rumble(which, rumble);
+                self.rumbles.insert(which, (revision, effect));
+            }
+        }
+    }
+
+    /// Plays the rumble on the controller, or returns `None` if the controller is gone or doesn't
+    /// support force feedback.
+    fn start_rumble(&mut self, which: u32, rumble: Rumble) -> Option<Effect> {
+        let id = self.indices.key_of(which)?;
+        if !self.gilrs_handle.connected_gamepad(id)?.is_ff_supported() {
+            return None;
+        }
+        let duration =
+            Ticks::from_ms(u32::try_from(rumble.duration.as_millis()).unwrap_or(u32::MAX));
+        let magnitude = |intensity: f32| (intensity.clamp(0.0, 1.0) * f32::from(u16::MAX)) as u16;
+        let scheduling = Replay {
+            play_for: duration,
+            ..Default::default()
+        };
+        let effect = EffectBuilder::new()
+            .add_effect(BaseEffect {
+                kind: BaseEffectType::Strong {
+                    magnitude: magnitude(rumble.strong),
+                },
+                scheduling,
+                envelope: Default::default(),
+            })
+            .add_effect(BaseEffect {
+                kind: BaseEffectType::Weak {
+                    magnitude: magnitude(rumble.weak),
+                },
+                scheduling,
+                envelope: Default::default(),
+            })
+            .repeat(Repeat::For(duration))
+            .gamepads(&[id])
+            .finish(&mut self.gilrs_handle)
+            .ok()?;
+        effect.play().ok()?;
+        Some(effect)
     }
 
     fn initialize_controllers(
@@ -186,22 +449,400 @@ impl<T: BindingTypes> GilrsEventsSystem<T> {
         handler: &mut InputHandler<T>,
         output: &mut EventChannel<InputEvent<T>>,
     ) {
-        use crate::controller::ControllerEvent::ControllerConnected;
+        let ids = self
+            .gilrs_handle
+            .gamepads()
+            .map(|(id, _gamepad)| id)
+            .collect::<Vec<_>>();
+        for id in ids {
+            for event in self.open_controller(id) {
+                if let Some(ref mut tracker) = self.idle_tracker {
+                    tracker.on_event(&event, Instant::now());
+                }
+                handler.send_controller_event(&event, output);
+            }
+        }
+    }
+
+    /// Returns the gilrs id of the controller currently opened under the given id, to look it up
+    /// in gilrs directly.
+    pub fn gamepad_id(&self, which: u32) -> Option<GamepadId> {
+        self.indices.key_of(which)
+    }
+}
+
+/// Hands out the ids of the opened controllers: the lowest id not in use, or the id a controller
+/// had before if it reconnects while that id is still free.
+#[derive(Debug)]
+struct ControllerIndices<K> {
+    opened: HashMap<K, u32>,
+    coalesced: HashMap<K, u32>,
+    previous: HashMap<K, u32>,
+}
 
-        for (_id, gamepad) in self.gilrs_handle.gamepads() {
-            let idx = self.my_hash(gamepad.id()) as u32;
-            self.opened_controllers.insert(gamepad.id(), idx);
-            handler.send_controller_event(&ControllerConnected { which: idx }, output);
+impl<K> Default for ControllerIndices<K> {
+    fn default() -> Self {
+        ControllerIndices {
+            opened: HashMap::new(),
+            coalesced: HashMap::new(),
+            previous: HashMap::new(),
         }
     }
+}
+
+impl<K: Copy + Eq + Hash> ControllerIndices<K> {
+    /// Returns the id the controller is opened under.
+    fn get(&self, key: K) -> Option<u32> {
+        self.opened
+            .get(&key)
+            .or_else(|| self.coalesced.get(&key))
+            .copied()
+    }
+
+    /// Returns the controller opened under the given id, preferring the one the id was allocated
+    /// for over coalesced duplicates.
+    fn key_of(&self, which: u32) -> Option<K> {
+        self.opened
+            .iter()
+            .chain(self.coalesced.iter())
+            .find(|&(_, &idx)| idx == which)
+            .map(|(&key, _)| key)
+    }
 
-    fn my_hash<U>(&self, obj: U) -> u64
-    where
-        U: Hash,
-    {
-        let mut hasher = DefaultHasher::new();
-        obj.hash(&mut hasher);
-        hasher.finish()
+    /// Returns true if the controller is opened as the duplicate of another one.
+    fn is_coalesced(&self, key: K) -> bool {
+        self.coalesced.contains_key(&key)
+    }
+
+    fn is_in_use(&self, which: u32) -> bool {
+        self.opened
+            .values()
+            .chain(self.coalesced.values())
+            .any(|&idx| idx == which)
+    }
+
+    /// Opens the controller under a free id and returns it.
+    fn allocate(&mut self, key: K) -> u32 {
+        if let Some(idx) = self.get(key) {
+            return idx;
+        }
+        let idx = match self.previous.get(&key) {
+            Some(&idx) if !self.is_in_use(idx) => idx,
+            _ => (0..)
+                .find(|&idx| !self.is_in_use(idx))
+                .expect("Ran out of controller ids"),
+        };
+        self.opened.insert(key, idx);
+        idx
+    }
+
+    /// Moves the controller onto the id of the controller it duplicates, freeing its own id.
+    fn coalesce(&mut self, key: K, which: u32) {
+        self.opened.remove(&key);
+        self.coalesced.insert(key, which);
+    }
+
+    /// Returns the opened controllers which aren't connected anymore and the connected controllers
+    /// which aren't opened yet.
+    fn reconcile(&self, connected: &[K]) -> (Vec<K>, Vec<K>) {
+        let gone = self
+            .opened
+            .keys()
+            .chain(self.coalesced.keys())
+            .filter(|key| !connected.contains(key))
+            .copied()
+            .collect();
+        let new = connected
+            .iter()
+            .filter(|&&key| self.get(key).is_none())
+            .copied()
+            .collect();
+        (gone, new)
+    }
+
+    /// Closes the controller and returns the id it was opened under.
+    ///
+    /// A duplicate coalesced onto the controller takes its place, so its input isn't dropped
+    /// anymore.
+    fn release(&mut self, key: K) -> Option<u32> {
+        if let Some(idx) = self.opened.remove(&key) {
+            self.previous.insert(key, idx);
+            let duplicate = self
+                .coalesced
+                .iter()
+                .find(|&(_, &which)| which == idx)
+                .map(|(&duplicate, _)| duplicate);
+            if let Some(duplicate) = duplicate {
+                self.coalesced.remove(&duplicate);
+                self.opened.insert(duplicate, idx);
+            }
+            return Some(idx);
+        }
+        self.coalesced.remove(&key)
+    }
+}
+
+/// Outcome of opening a controller.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OpenedController {
+    /// The controller is opened under its own id.
+    New(u32),
+    /// The controller duplicates the controller with the given id and is opened under that id.
+    Coalesced(u32),
+    /// The controller duplicates another controller but is opened under its own id.
+    Duplicate { which: u32, duplicate_of: u32 },
+}
+
+impl OpenedController {
+    /// Returns the id the controller is opened under.
+    fn which(self) -> u32 {
+        match self {
+            OpenedController::New(which)
+            | OpenedController::Coalesced(which)
+            | OpenedController::Duplicate { which, .. } => which,
+        }
+    }
+
+    /// Returns the events to emit for the opened controller.
+    fn events(self) -> SmallVec<[ControllerEvent; 2]> {
+        match self {
+            OpenedController::New(which) => {
+                smallvec![ControllerEvent::ControllerConnected { which }]
+            }
+            OpenedController::Coalesced(_) => SmallVec::new(),
+            OpenedController::Duplicate {
+                which,
+                duplicate_of,
+            } => smallvec![
+                ControllerEvent::ControllerConnected { which },
+                ControllerEvent::ControllerDuplicateDetected {
+                    which,
+                    duplicate_of,
+                },
+            ],
+        }
+    }
+}
+
+/// Uuid and name of the opened controllers, used to spot a device surfaced more than once.
+#[derive(Debug, Default)]
+struct ControllerIdentities {
+    identities: Vec<(u32, [u8; 16], String)>,
+}
+
+impl ControllerIdentities {
+    fn open(
+        &mut self,
+        policy: DuplicateControllerPolicy,
+        which: u32,
+        uuid: [u8; 16],
+        name: String,
+    ) -> OpenedController {
+        let original = self
+            .identities
+            .iter()
+            .find(|(idx, other_uuid, other_name)| {
+                *idx != which && *other_uuid == uuid && *other_name == name
+            })
+            .map(|&(idx, _, _)| idx);
+        match (original, policy) {
+            (Some(original), DuplicateControllerPolicy::Coalesce) => {
+                OpenedController::Coalesced(original)
+            }
+            (Some(duplicate_of), DuplicateControllerPolicy::Report) => {
+                self.identities.push((which, uuid, name));
+                OpenedController::Duplicate {
+                    which,
+                    duplicate_of,
+                }
+            }
+            (None, _) => {
+                if self.identities.iter().all(|&(idx, _, _)| idx != which) {
+                    self.identities.push((which, uuid, name));
+                }
+                OpenedController::New(which)
+            }
+        }
+    }
+
+    fn remove(&mut self, which: u32) {
+        self.identities.retain(|&(idx, _, _)| idx != which);
+    }
+}
+
+/// The gilrs buttons and axes which map onto a `ControllerButton` and `ControllerAxis`.
+const SYNCED_BUTTONS: [Button; 17] = [
+    Button::South,
+    Button::East,
+    Button::West,
+    Button::North,
+    Button::DPadDown,
+    Button::DPadLeft,
+    Button::DPadRight,
+    Button::DPadUp,
+    Button::LeftTrigger,
+    Button::RightTrigger,
+    Button::LeftThumb,
+    Button::RightThumb,
+    Button::Select,
+    Button::Start,
+    Button::Mode,
+    Button::LeftTrigger2,
+    Button::RightTrigger2,
+];
+const SYNCED_AXES: [Axis; 6] = [
+    Axis::LeftStickX,
+    Axis::LeftStickY,
+    Axis::RightStickX,
+    Axis::RightStickY,
+    Axis::LeftZ,
+    Axis::RightZ,
+];
+
+/// Tells when the wall clock jumped between two runs, e.g. because the system resumed from sleep.
+struct ResumeDetector {
+    gap: Duration,
+    last_run: Option<SystemTime>,
+}
+
+impl ResumeDetector {
+    fn new(gap: Duration) -> Self {
+        ResumeDetector {
+            gap,
+            last_run: None,
+        }
+    }
+
+    /// Records a run, returning true if at least the gap passed since the previous one.
+    fn resumed(&mut self, now: SystemTime) -> bool {
+        let resumed = match self.last_run.map(|last_run| now.duration_since(last_run)) {
+            Some(Ok(elapsed)) => elapsed >= self.gap,
+            // The clock went backwards or this is the first run
+            _ => false,
+        };
+        self.last_run = Some(now);
+        resumed
+    }
+}
+
+/// Tracks the last input of each controller to tell when it becomes idle.
+struct IdleTracker {
+    idle_after: Duration,
+    controllers: HashMap<u32, (Instant, bool)>,
+}
+
+impl IdleTracker {
+    fn new(idle_after: Duration) -> Self {
+        IdleTracker {
+            idle_after,
+            controllers: HashMap::new(),
+        }
+    }
+
+    /// Records the event, returning a `ControllerActive` event if it is input of an idle
+    /// controller.
+    fn on_event(&mut self, event: &ControllerEvent, now: Instant) -> Option<ControllerEvent> {
+        use self::ControllerEvent::*;
+
+        match *event {
+            ControllerConnected { which } => {
+                self.controllers.insert(which, (now, false));
+                None
+            }
+            ControllerDisconnected { which } => {
+                self.controllers.remove(&which);
+                None
+            }
+            ControllerAxisMoved { which, .. }
+            | ControllerButtonPressed { which, .. }
+            | ControllerButtonReleased { which, .. } => {
+                let (last_input, idle) = self.controllers.entry(which).or_insert((now, false));
+                *last_input = now;
+                if std::mem::replace(idle, false) {
+                    Some(ControllerActive { which })
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns `ControllerIdle` events for the controllers which just became idle.
+    fn poll(&mut self, now: Instant) -> Vec<ControllerEvent> {
+        let idle_after = self.idle_after;
+        self.controllers
+            .iter_mut()
+            .filter(|(_, (last_input, idle))| {
+                !*idle && now.duration_since(*last_input) >= idle_after
+            })
+            .map(|(&which, (_, idle))| {
+                *idle = true;
+                ControllerEvent::ControllerIdle { which }
+            })
+            .collect()
+    }
+}
+
+/// Caps how often axis events are emitted for each controller axis, holding back the latest value
+/// of changes that arrive too quickly so it is never lost.
+struct AxisRateLimiter {
+    interval: Duration,
+    axes: HashMap<(u32, ControllerAxis), (Instant, Option<f32>)>,
+}
+
+impl AxisRateLimiter {
+    fn new(interval: Duration) -> Self {
+        AxisRateLimiter {
+            interval,
+            axes: HashMap::new(),
+        }
+    }
+
+    /// Returns the value if it can be emitted right away, otherwise holds it back.
+    fn filter(
+        &mut self,
+        which: u32,
+        axis: ControllerAxis,
+        value: f32,
+        now: Instant,
+    ) -> Option<f32> {
+        let interval = self.interval;
+        match self.axes.get_mut(&(which, axis)) {
+            Some((last_emitted, pending)) if now.duration_since(*last_emitted) < interval => {
+                *pending = Some(value);
+                None
+            }
+            Some((last_emitted, pending)) => {
+                *last_emitted = now;
+                *pending = None;
+                Some(value)
+            }
+            None => {
+                self.axes.insert((which, axis), (now, None));
+                Some(value)
+            }
+        }
+    }
+
+    /// Returns the held back values whose axis may emit again.
+    fn flush(&mut self, now: Instant) -> Vec<(u32, ControllerAxis, f32)> {
+        let interval = self.interval;
+        self.axes
+            .iter_mut()
+            .filter(|(_, (last_emitted, _))| now.duration_since(*last_emitted) >= interval)
+            .filter_map(|(&(which, axis), (last_emitted, pending))| {
+                pending.take().map(|value| {
+                    *last_emitted = now;
+                    (which, axis, value)
+                })
+            })
+            .collect()
+    }
+
+    /// Drops all state kept for the given controller.
+    fn forget(&mut self, which: u32) {
+        self.axes.retain(|&(idx, _), _| idx != which);
     }
 }
 
@@ -243,3 +884,225 @@ impl From<Axis> for ControllerAxis {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicate_controller_is_reported_or_coalesced() {
+        let uuid = [7; 16];
+        let name = "Gamepad".to_owned();
+
+        let mut identities = ControllerIdentities::default();
+        let policy = DuplicateControllerPolicy::Report;
+        assert_eq!(
+            identities.open(policy, 1, uuid, name.clone()),
+            OpenedController::New(1)
+        );
+        let duplicate = identities.open(policy, 2, uuid, name.clone());
+        assert_eq!(
+            duplicate,
+            OpenedController::Duplicate {
+                which: 2,
+                duplicate_of: 1
+            }
+        );
+        assert_eq!(
+            duplicate.events().to_vec(),
+            vec![
+                ControllerEvent::ControllerConnected { which: 2 },
+                ControllerEvent::ControllerDuplicateDetected {
+                    which: 2,
+                    duplicate_of: 1
+                },
+            ]
+        );
+
+        let mut identities = ControllerIdentities::default();
+        let policy = DuplicateControllerPolicy::Coalesce;
+        identities.open(policy, 1, uuid, name.clone());
+        let coalesced = identities.open(policy, 2, uuid, name.clone());
+        assert_eq!(coalesced, OpenedController::Coalesced(1));
+        assert_eq!(coalesced.which(), 1);
+        assert!(coalesced.events().is_empty());
+
+        // A different device is never considered a duplicate
+        assert_eq!(
+            identities.open(policy, 3, [8; 16], name),
+            OpenedController::New(3)
+        );
+    }
+
+    #[test]
+    fn controller_ids_are_allocated_in_connection_order() {
+        let mut indices = ControllerIndices::default();
+        assert_eq!(indices.allocate('a'), 0);
+        assert_eq!(indices.allocate('b'), 1);
+        assert_eq!(indices.allocate('c'), 2);
+        assert_eq!(indices.allocate('b'), 1);
+        assert_eq!(indices.key_of(1), Some('b'));
+
+        // The lowest free id is reused
+        assert_eq!(indices.release('b'), Some(1));
+        assert_eq!(indices.key_of(1), None);
+        assert_eq!(indices.allocate('d'), 1);
+
+        // A reconnecting controller gets its previous id back while it is free
+        assert_eq!(indices.release('c'), Some(2));
+        assert_eq!(indices.release('a'), Some(0));
+        assert_eq!(indices.allocate('c'), 2);
+        assert_eq!(indices.allocate('e'), 0);
+        assert_eq!(indices.allocate('a'), 3);
+    }
+
+    #[test]
+    fn coalesced_controller_frees_its_own_id() {
+        let mut indices = ControllerIndices::default();
+        assert_eq!(indices.allocate('a'), 0);
+        assert_eq!(indices.allocate('b'), 1);
+        indices.coalesce('b', 0);
+        assert_eq!(indices.get('b'), Some(0));
+        assert!(indices.is_coalesced('b'));
+        assert!(!indices.is_coalesced('a'));
+        assert_eq!(indices.key_of(0), Some('a'));
+        assert_eq!(indices.allocate('c'), 1);
+
+        assert_eq!(indices.release('a'), Some(0));
+        assert!(indices.is_in_use(0));
+        assert_eq!(indices.key_of(0), Some('b'));
+        assert_eq!(indices.release('b'), Some(0));
+        assert!(!indices.is_in_use(0));
+    }
+
+    #[test]
+    fn coalesced_controller_takes_over_from_its_original() {
+        let mut indices = ControllerIndices::default();
+        indices.allocate('a');
+        indices.allocate('b');
+        indices.coalesce('b', 0);
+
+        assert_eq!(indices.release('a'), Some(0));
+        assert!(!indices.is_coalesced('b'));
+        assert_eq!(indices.get('b'), Some(0));
+        assert_eq!(indices.key_of(0), Some('b'));
+        assert_eq!(indices.release('b'), Some(0));
+        assert!(!indices.is_in_use(0));
+    }
+
+    #[test]
+    fn controllers_are_reconciled_after_resume() {
+        let gap = Duration::from_secs(5);
+        let mut detector = ResumeDetector::new(gap);
+        let start = SystemTime::now();
+        assert!(!detector.resumed(start));
+        assert!(!detector.resumed(start + Duration::from_millis(16)));
+        assert!(detector.resumed(start + Duration::from_millis(16) + gap));
+        assert!(!detector.resumed(start));
+
+        let mut indices = ControllerIndices::default();
+        indices.allocate('a');
+        indices.allocate('b');
+        indices.allocate('c');
+        indices.coalesce('c', 0);
+        // While asleep 'a' and its duplicate went away and 'd' was plugged in. The backlog is
+        // discarded, so only the currently connected set is known.
+        let connected = ['b', 'd'];
+        let (mut gone, new) = indices.reconcile(&connected);
+        gone.sort_unstable();
+        assert_eq!(gone, vec!['a', 'c']);
+        assert_eq!(new, vec!['d']);
+        for key in gone {
+            indices.release(key);
+        }
+        for key in new {
+            indices.allocate(key);
+        }
+
+        assert_eq!(indices.reconcile(&connected), (vec![], vec![]));
+        assert_eq!(indices.get('b'), Some(1));
+        assert_eq!(indices.get('d'), Some(0));
+        assert_eq!(indices.get('a'), None);
+        assert!(!indices.is_in_use(2));
+    }
+
+    #[test]
+    fn idle_controller_becomes_active_on_input() {
+        let idle_after = Duration::from_millis(50);
+        let mut tracker = IdleTracker::new(idle_after);
+        let start = Instant::now();
+        let press = ControllerEvent::ControllerButtonPressed {
+            which: 0,
+            button: ControllerButton::A,
+        };
+
+        tracker.on_event(&ControllerEvent::ControllerConnected { which: 0 }, start);
+        assert_eq!(tracker.on_event(&press, start), None);
+        assert!(tracker.poll(start + idle_after / 2).is_empty());
+        assert_eq!(
+            tracker.poll(start + idle_after),
+            vec![ControllerEvent::ControllerIdle { which: 0 }]
+        );
+        // Idle is only reported once
+        assert!(tracker.poll(start + idle_after * 2).is_empty());
+
+        assert_eq!(
+            tracker.on_event(&press, start + idle_after * 2),
+            Some(ControllerEvent::ControllerActive { which: 0 })
+        );
+        assert_eq!(tracker.on_event(&press, start + idle_after * 2), None);
+        assert!(tracker.poll(start + idle_after * 5 / 2).is_empty());
+
+        tracker.on_event(&ControllerEvent::ControllerDisconnected { which: 0 }, start);
+        assert!(tracker.poll(start + idle_after * 10).is_empty());
+    }
+
+    #[test]
+    fn axis_rate_limiter_caps_emissions_and_keeps_last_value() {
+        let interval = Duration::from_millis(10);
+        let mut limiter = AxisRateLimiter::new(interval);
+        let start = Instant::now();
+
+        // 100 changes within 50ms, one every 0.5ms
+        let mut emitted = Vec::new();
+        for i in 0..100u32 {
+            let now = start + Duration::from_micros(500 * u64::from(i));
+            let value = i as f32 / 100.0;
+            if let Some(value) = limiter.filter(0, ControllerAxis::LeftX, value, now) {
+                emitted.push(value);
+            }
+            emitted.extend(limiter.flush(now).into_iter().map(|(_, _, value)| value));
+        }
+        // At most one event per 10ms over the 50ms burst.
+        assert_eq!(emitted, vec![0.0, 0.2, 0.4, 0.6, 0.8]);
+
+        // Once the interval elapses the latest value goes out.
+        let end = start + Duration::from_micros(500 * 99);
+        for (_, _, value) in limiter.flush(end + interval) {
+            emitted.push(value);
+        }
+        assert_eq!(emitted.len(), 6);
+        assert_eq!(emitted.last(), Some(&0.99));
+        assert!(limiter.flush(end + interval * 2).is_empty());
+    }
+
+    #[test]
+    fn axis_rate_limiter_tracks_axes_independently() {
+        let mut limiter = AxisRateLimiter::new(Duration::from_millis(10));
+        let now = Instant::now();
+
+        assert_eq!(
+            limiter.filter(0, ControllerAxis::LeftX, 0.1, now),
+            Some(0.1)
+        );
+        assert_eq!(
+            limiter.filter(0, ControllerAxis::LeftY, 0.2, now),
+            Some(0.2)
+        );
+        assert_eq!(
+            limiter.filter(1, ControllerAxis::LeftX, 0.3, now),
+            Some(0.3)
+        );
+        assert_eq!(limiter.filter(0, ControllerAxis::LeftX, 0.4, now), None);
+    }
+}
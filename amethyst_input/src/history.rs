@@ -0,0 +1,176 @@
+//! Recording of the most recent input events for diagnostics.
+use derivative::Derivative;
+use derive_new::new;
+use std::{collections::VecDeque, fmt::Write as _, marker::PhantomData, time::Instant};
+
+use crate::{BindingTypes, InputEvent};
+use amethyst_core::{
+    ecs::{
+        prelude::{Read, System, World, Write},
+        SystemData,
+    },
+    shrev::{EventChannel, ReaderId},
+    SystemDesc,
+};
+
+/// Number of events kept by a default `InputHistory`.
+const DEFAULT_CAPACITY: usize = 256;
+
+/// Ring buffer of the most recent `InputEvent`s along with the time at which they were recorded.
+///
+/// Once full, recording an event discards the oldest one. This is meant to be dumped when
+/// something goes wrong, e.g. in a panic hook, to help reproducing the issue.
+#[derive(Debug, Derivative)]
+#[derivative(Default(bound = ""))]
+pub struct InputHistory<T: BindingTypes> {
+    #[derivative(Default(value = "DEFAULT_CAPACITY"))]
+    capacity: usize,
+    events: VecDeque<(Instant, InputEvent<T>)>,
+}
+
+impl<T: BindingTypes> InputHistory<T> {
+    /// Creates a new `InputHistory` keeping at most `capacity` events.
+    pub fn new(capacity: usize) -> Self {
+        InputHistory {
+            capacity,
+            events: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the maximum number of events kept.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Sets the maximum number of events kept, discarding the oldest events if needed.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.events.len() > capacity {
+            self.events.pop_front();
+        }
+    }
+
+    /// Returns the number of events currently kept.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Returns true if no event has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Records an event at the given time, discarding the oldest event if the history is full.
+    pub fn record(&mut self, time: Instant, event: InputEvent<T>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back((time, event));
+    }
+
+    /// Returns an iterator over the recorded events, from the oldest to the most recent.
+    pub fn iter(&self) -> impl Iterator<Item = &(Instant, InputEvent<T>)> {
+        self.events.iter()
+    }
+
+    /// Removes all the recorded events.
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    /// Formats the recorded events, from the oldest to the most recent, one per line along with
+    /// how long ago they were recorded.
+    pub fn dump(&self) -> String {
+        let now = Instant::now();
+        let mut dump = String::new();
+        for (time, event) in &self.events {
+            let _ = writeln!(dump, "{:?} ago: {:?}", now.duration_since(*time), event);
+        }
+        dump
+    }
+}
+
+/// Builds an `InputHistorySystem`.
+#[derive(Debug, new)]
+pub struct InputHistorySystemDesc<T>
+where
+    T: BindingTypes,
+{
+    capacity: usize,
+    #[new(default)]
+    marker: PhantomData<T>,
+}
+
+impl<'a, 'b, T> SystemDesc<'a, 'b, InputHistorySystem<T>> for InputHistorySystemDesc<T>
+where
+    T: BindingTypes,
+{
+    fn build(self, world: &mut World) -> InputHistorySystem<T> {
+        <InputHistorySystem<T> as System<'_>>::SystemData::setup(world);
+
+        world.insert(InputHistory::<T>::new(self.capacity));
+        let reader = world
+            .fetch_mut::<EventChannel<InputEvent<T>>>()
+            .register_reader();
+
+        InputHistorySystem { reader }
+    }
+}
+
+/// System recording every `InputEvent` in the `InputHistory` resource.
+#[derive(Debug)]
+pub struct InputHistorySystem<T>
+where
+    T: BindingTypes,
+{
+    reader: ReaderId<InputEvent<T>>,
+}
+
+impl<'a, T: BindingTypes> System<'a> for InputHistorySystem<T> {
+    type SystemData = (
+        Read<'a, EventChannel<InputEvent<T>>>,
+        Write<'a, InputHistory<T>>,
+    );
+
+    fn run(&mut self, (events, mut history): Self::SystemData) {
+        let now = Instant::now();
+        for event in events.read(&mut self.reader) {
+            history.record(now, event.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ControllerButton, StringBindings};
+    use amethyst_core::ecs::{RunNow, WorldExt};
+
+    #[test]
+    fn history_keeps_most_recent_events_in_order() {
+        let mut world = World::new();
+        world.insert(EventChannel::<InputEvent<StringBindings>>::new());
+        let mut system = InputHistorySystemDesc::<StringBindings>::new(3).build(&mut world);
+
+        let events = (0..5)
+            .map(|which| InputEvent::ControllerButtonPressed {
+                which,
+                button: ControllerButton::A,
+            })
+            .collect::<Vec<_>>();
+        world
+            .fetch_mut::<EventChannel<InputEvent<StringBindings>>>()
+            .iter_write(events.iter().cloned());
+        system.run_now(&world);
+
+        let history = world.fetch::<InputHistory<StringBindings>>();
+        assert_eq!(history.len(), 3);
+        assert_eq!(
+            history.iter().map(|(_, event)| event).collect::<Vec<_>>(),
+            events[2..].iter().collect::<Vec<_>>()
+        );
+    }
+}
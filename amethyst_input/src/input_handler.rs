@@ -9,7 +9,12 @@ use super::{
 use amethyst_core::shrev::EventChannel;
 use derivative::Derivative;
 use smallvec::SmallVec;
-use std::{borrow::Borrow, hash::Hash};
+use std::{
+    borrow::Borrow,
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult},
+    hash::Hash,
+};
 use winit::{
     dpi::LogicalPosition, DeviceEvent, ElementState, Event, KeyboardInput, MouseButton,
     MouseScrollDelta, VirtualKeyCode, WindowEvent,
@@ -37,6 +42,12 @@ where
     /// First number represents mapped ID visible to the user code,
     /// while second is the ID used by incoming events.
     connected_controllers: SmallVec<[(u32, u32); 8]>,
+    /// Controller axes driven by a pair of buttons, as (negative, positive, axis).
+    emulated_controller_axes: SmallVec<[(ControllerButton, ControllerButton, ControllerAxis); 4]>,
+    /// Dead zones applied to the value of `ControllerAxisMoved` events, as (axis, dead zone).
+    controller_axis_dead_zones: SmallVec<[(ControllerAxis, f32); 4]>,
+    /// Trigger axes driving their digital button, as (controller id, axis, threshold).
+    trigger_thresholds: SmallVec<[(u32, ControllerAxis, TriggerThreshold); 4]>,
     mouse_last_position: Option<(f32, f32)>,
     mouse_position: Option<(f32, f32)>,
     mouse_wheel_vertical: f32,
@@ -298,74 +309,33 @@ where
                         .unwrap_or_else(|| {
                             self.controller_axes.push((controller_id, axis, value));
                         });
-                    event_handler.single_write(event.into());
+                    let dead_zone = self
+                        .controller_axis_dead_zones
+                        .iter()
+                        .find(|&&(a, _)| a == axis)
+                        .map(|&(_, dead_zone)| dead_zone)
+                        .unwrap_or(0.0);
+                    event_handler.single_write(InputEvent::ControllerAxisMoved {
+                        which,
+                        axis,
+                        value: apply_dead_zone(value, dead_zone),
+                        raw: value,
+                    });
+                    self.update_trigger_button(which, controller_id, axis, value, event_handler);
                 }
             }
             ControllerButtonPressed { which, button } => {
                 if let Some(controller_id) = self.controller_idx_to_id(which) {
-                    if self
-                        .pressed_controller_buttons
-                        .iter()
-                        .all(|&(id, b)| id != controller_id || b != button)
-                    {
-                        self.pressed_controller_buttons
-                            .push((controller_id, button));
-                        event_handler.iter_write(
-                            [
-                                event.into(),
-                                ButtonPressed(Button::Controller(controller_id, button)),
-                            ]
-                            .iter()
-                            .cloned(),
-                        );
-                        for (action, combinations) in self.bindings.actions.iter() {
-                            for combination in combinations
-                                .iter()
-                                .filter(|c| c.contains(&Button::Controller(controller_id, button)))
-                            {
-                                if combination
-                                    .iter()
-                                    .all(|button| self.button_is_down(*button))
-                                {
-                                    event_handler.single_write(ActionPressed(action.clone()));
-                                }
-                            }
-                        }
+                    // Triggers with a threshold are pressed by their axis instead
+                    if !self.has_trigger_threshold(controller_id, button) {
+                        self.press_controller_button(which, controller_id, button, event_handler);
                     }
                 }
             }
             ControllerButtonReleased { which, button } => {
                 if let Some(controller_id) = self.controller_idx_to_id(which) {
-                    let index = self
-                        .pressed_controller_buttons
-                        .iter()
-                        .position(|&(id, b)| id == controller_id && b == button);
-                    if let Some(i) = index {
-                        self.pressed_controller_buttons.swap_remove(i);
-                        event_handler.iter_write(
-                            [
-                                event.into(),
-                                ButtonReleased(Button::Controller(controller_id, button)),
-                            ]
-                            .iter()
-                            .cloned(),
-                        );
-                        for (action, combinations) in self.bindings.actions.iter() {
-                            for combination in combinations {
-                                if combination.contains(&Button::Controller(controller_id, button))
-                                {
-                                    let down = combination
-                                        .iter()
-                                        .filter(|b| {
-                                            b != &&Button::Controller(controller_id, button)
-                                        })
-                                        .all(|b| self.button_is_down(*b));
-                                    if down {
-                                        event_handler.single_write(ActionReleased(action.clone()));
-                                    }
-                                }
-                            }
-                        }
+                    if !self.has_trigger_threshold(controller_id, button) {
+                        self.release_controller_button(which, controller_id, button, event_handler);
                     }
                 }
             }
@@ -395,9 +365,90 @@ where
                     }
                 }
             }
+            ControllerDuplicateDetected { .. } => {
+                event_handler.single_write(event.into());
+            }
+            ControllerIdle { which } | ControllerActive { which } => {
+                if self.controller_idx_to_id(which).is_some() {
+                    event_handler.single_write(event.into());
+                }
+            }
         }
     }
 
+    /// Drives a controller axis with a pair of buttons, e.g. the left stick X axis with
+    /// `DPadLeft` and `DPadRight`, so gameplay can read one axis regardless of input device.
+    ///
+    /// Pressing a button emits a synthetic `ControllerAxisMoved` event moving the axis to -1 for the
+    /// negative button or 1 for the positive one. While both buttons are held the axis rests at 0,
+    /// and releasing one of them moves the axis to the value of the button still held.
+    pub fn emulate_controller_axis(
+        &mut self,
+        negative: ControllerButton,
+        positive: ControllerButton,
+        axis: ControllerAxis,
+    ) {
+        self.emulated_controller_axes
+            .retain(|&mut (_, _, a)| a != axis);
+        self.emulated_controller_axes
+            .push((negative, positive, axis));
+    }
+
+    /// Stops driving the given controller axis with buttons.
+    pub fn remove_controller_axis_emulation(&mut self, axis: ControllerAxis) {
+        self.emulated_controller_axes
+            .retain(|&mut (_, _, a)| a != axis);
+    }
+
+    /// Applies a dead zone to the `value` of the `ControllerAxisMoved` events of the given axis,
+    /// treating values from -dead_zone to dead_zone as 0 and scaling the others to the full
+    /// range. The events keep the value reported by the controller in `raw`.
+    ///
+    /// The dead zone is clamped to `0.0..=1.0`, a dead zone of 1 treating every value as 0.
+    ///
+    /// This doesn't affect axis bindings, which have their own dead zone.
+    pub fn set_controller_axis_dead_zone(&mut self, axis: ControllerAxis, dead_zone: f32) {
+        debug_assert!(
+            (0.0..=1.0).contains(&dead_zone),
+            "Controller axis dead zone out of range: {}",
+            dead_zone
+        );
+        let dead_zone = dead_zone.clamp(0.0, 1.0);
+        self.controller_axis_dead_zones
+            .retain(|&mut (a, _)| a != axis);
+        self.controller_axis_dead_zones.push((axis, dead_zone));
+    }
+
+    /// Stops applying a dead zone to the `ControllerAxisMoved` events of the given axis.
+    pub fn remove_controller_axis_dead_zone(&mut self, axis: ControllerAxis) {
+        self.controller_axis_dead_zones
+            .retain(|&mut (a, _)| a != axis);
+    }
+
+    /// Presses and releases the digital button of a trigger axis of the given controller as the
+    /// trigger crosses the points of the threshold, emitting the usual button events.
+    ///
+    /// Only `ControllerAxis::LeftTrigger` and `ControllerAxis::RightTrigger` drive a button, which
+    /// is compared against the raw axis value. The button events the controller system emits for
+    /// the trigger at its own threshold are ignored meanwhile.
+    pub fn set_trigger_threshold(
+        &mut self,
+        controller_id: u32,
+        trigger: ControllerAxis,
+        threshold: TriggerThreshold,
+    ) {
+        self.trigger_thresholds
+            .retain(|&mut (id, a, _)| id != controller_id || a != trigger);
+        self.trigger_thresholds
+            .push((controller_id, trigger, threshold));
+    }
+
+    /// Stops driving the digital button of a trigger axis of the given controller.
+    pub fn remove_trigger_threshold(&mut self, controller_id: u32, trigger: ControllerAxis) {
+        self.trigger_thresholds
+            .retain(|&mut (id, a, _)| id != controller_id || a != trigger);
+    }
+
     /// This function is to be called whenever a frame begins. It resets some input values.
     ///
     /// The `InputSystem` will call this automatically. If you're using that system, you
@@ -484,6 +535,83 @@ where
             .any(|ids| ids.0 == controller_id)
     }
 
+    /// Serializes the connected controllers with their pressed buttons and axis values into a
+    /// compact snapshot, e.g. to save a frame's input for rollback. The same state always
+    /// produces the same bytes.
+    pub fn serialize_controller_state(&self) -> Vec<u8> {
+        let mut controllers = self.connected_controllers.to_vec();
+        controllers.sort_unstable();
+
+        let mut snapshot = Vec::new();
+        snapshot.extend_from_slice(&(controllers.len() as u32).to_le_bytes());
+        for (controller_id, which) in controllers {
+            snapshot.extend_from_slice(&controller_id.to_le_bytes());
+            snapshot.extend_from_slice(&which.to_le_bytes());
+            let buttons = ControllerButton::ALL
+                .iter()
+                .enumerate()
+                .filter(|&(_, &button)| self.controller_button_is_down(controller_id, button))
+                .fold(0u32, |buttons, (i, _)| buttons | 1 << i);
+            snapshot.extend_from_slice(&buttons.to_le_bytes());
+            let mut axes = self
+                .controller_axes
+                .iter()
+                .filter(|&&(id, _, _)| id == controller_id)
+                .filter_map(|&(_, axis, value)| {
+                    let axis = ControllerAxis::ALL.iter().position(|&a| a == axis)?;
+                    Some((axis as u8, value))
+                })
+                .collect::<SmallVec<[_; 8]>>();
+            axes.sort_unstable_by_key(|&(axis, _)| axis);
+            snapshot.push(axes.len() as u8);
+            for (axis, value) in axes {
+                snapshot.push(axis);
+                snapshot.extend_from_slice(&value.to_bits().to_le_bytes());
+            }
+        }
+        snapshot
+    }
+
+    /// Replaces the state of all controllers with a snapshot taken by
+    /// `serialize_controller_state`. No events are emitted. The state is left untouched if the
+    /// snapshot is malformed.
+    pub fn apply_controller_state(&mut self, snapshot: &[u8]) -> Result<(), ControllerStateError> {
+        let mut reader = SnapshotReader(snapshot);
+        let mut connected_controllers = SmallVec::new();
+        let mut pressed_controller_buttons = SmallVec::new();
+        let mut controller_axes = SmallVec::new();
+        for _ in 0..reader.u32()? {
+            let controller_id = reader.u32()?;
+            connected_controllers.push((controller_id, reader.u32()?));
+            let buttons = reader.u32()?;
+            if buttons >> ControllerButton::ALL.len() != 0 {
+                return Err(ControllerStateError::UnknownButtons(buttons));
+            }
+            pressed_controller_buttons.extend(
+                ControllerButton::ALL
+                    .iter()
+                    .enumerate()
+                    .filter(|&(i, _)| buttons & 1 << i != 0)
+                    .map(|(_, &button)| (controller_id, button)),
+            );
+            for _ in 0..reader.u8()? {
+                let axis = reader.u8()?;
+                let axis = *ControllerAxis::ALL
+                    .get(usize::from(axis))
+                    .ok_or(ControllerStateError::UnknownAxis(axis))?;
+                controller_axes.push((controller_id, axis, f32::from_bits(reader.u32()?)));
+            }
+        }
+        if !reader.0.is_empty() {
+            return Err(ControllerStateError::TrailingBytes(reader.0.len()));
+        }
+
+        self.connected_controllers = connected_controllers;
+        self.pressed_controller_buttons = pressed_controller_buttons;
+        self.controller_axes = controller_axes;
+        Ok(())
+    }
+
     /// Gets the current mouse position.
     ///
     /// this method can return None, either if no mouse is connected, or if no mouse events have
@@ -538,16 +666,7 @@ where
                 .iter()
                 .find(|&&(id, a, _)| id == *controller_id && a == *axis)
                 .map(|&(_, _, val)| if *invert { -val } else { val })
-                .map(|val| {
-                    let dead_zone = *dead_zone as f32;
-                    if val < -dead_zone {
-                        (val + dead_zone) / (1.0 - dead_zone)
-                    } else if val > dead_zone {
-                        (val - dead_zone) / (1.0 - dead_zone)
-                    } else {
-                        0.0
-                    }
-                })
+                .map(|val| apply_dead_zone(val, *dead_zone as f32))
                 .unwrap_or(0.0),
             Axis::Mouse {
                 axis,
@@ -609,6 +728,146 @@ where
         })
     }
 
+    /// Emits a synthetic axis event for each axis emulated with the given button.
+    fn update_emulated_controller_axes(
+        &mut self,
+        which: u32,
+        controller_id: u32,
+        button: ControllerButton,
+        event_handler: &mut EventChannel<InputEvent<T>>,
+    ) {
+        let emulated = self
+            .emulated_controller_axes
+            .iter()
+            .filter(|&&(neg, pos, _)| neg == button || pos == button)
+            .map(|&(neg, pos, axis)| {
+                let value = match (
+                    self.controller_button_is_down(controller_id, pos),
+                    self.controller_button_is_down(controller_id, neg),
+                ) {
+                    (true, false) => 1.0,
+                    (false, true) => -1.0,
+                    _ => 0.0,
+                };
+                ControllerEvent::ControllerAxisMoved { which, axis, value }
+            })
+            .collect::<SmallVec<[_; 4]>>();
+        for event in emulated.iter() {
+            self.send_controller_event(event, event_handler);
+        }
+    }
+
+    fn press_controller_button(
+        &mut self,
+        which: u32,
+        controller_id: u32,
+        button: ControllerButton,
+        event_handler: &mut EventChannel<InputEvent<T>>,
+    ) {
+        if self
+            .pressed_controller_buttons
+            .iter()
+            .all(|&(id, b)| id != controller_id || b != button)
+        {
+            self.pressed_controller_buttons
+                .push((controller_id, button));
+            event_handler.iter_write(
+                [
+                    InputEvent::ControllerButtonPressed { which, button },
+                    ButtonPressed(Button::Controller(controller_id, button)),
+                ]
+                .iter()
+                .cloned(),
+            );
+            for (action, combinations) in self.bindings.actions.iter() {
+                for combination in combinations
+                    .iter()
+                    .filter(|c| c.contains(&Button::Controller(controller_id, button)))
+                {
+                    if combination
+                        .iter()
+                        .all(|button| self.button_is_down(*button))
+                    {
+                        event_handler.single_write(ActionPressed(action.clone()));
+                    }
+                }
+            }
+            self.update_emulated_controller_axes(which, controller_id, button, event_handler);
+        }
+    }
+
+    fn release_controller_button(
+        &mut self,
+        which: u32,
+        controller_id: u32,
+        button: ControllerButton,
+        event_handler: &mut EventChannel<InputEvent<T>>,
+    ) {
+        let index = self
+            .pressed_controller_buttons
+            .iter()
+            .position(|&(id, b)| id == controller_id && b == button);
+        if let Some(i) = index {
+            self.pressed_controller_buttons.swap_remove(i);
+            event_handler.iter_write(
+                [
+                    InputEvent::ControllerButtonReleased { which, button },
+                    ButtonReleased(Button::Controller(controller_id, button)),
+                ]
+                .iter()
+                .cloned(),
+            );
+            for (action, combinations) in self.bindings.actions.iter() {
+                for combination in combinations {
+                    if combination.contains(&Button::Controller(controller_id, button)) {
+                        let down = combination
+                            .iter()
+                            .filter(|b| b != &&Button::Controller(controller_id, button))
+                            .all(|b| self.button_is_down(*b));
+                        if down {
+                            event_handler.single_write(ActionReleased(action.clone()));
+                        }
+                    }
+                }
+            }
+            self.update_emulated_controller_axes(which, controller_id, button, event_handler);
+        }
+    }
+
+    fn has_trigger_threshold(&self, controller_id: u32, button: ControllerButton) -> bool {
+        self.trigger_thresholds
+            .iter()
+            .any(|&(id, axis, _)| id == controller_id && axis.trigger_button() == Some(button))
+    }
+
+    fn update_trigger_button(
+        &mut self,
+        which: u32,
+        controller_id: u32,
+        axis: ControllerAxis,
+        value: f32,
+        event_handler: &mut EventChannel<InputEvent<T>>,
+    ) {
+        let button = match axis.trigger_button() {
+            Some(button) => button,
+            None => return,
+        };
+        let threshold = match self
+            .trigger_thresholds
+            .iter()
+            .find(|&&(id, a, _)| id == controller_id && a == axis)
+        {
+            Some(&(_, _, threshold)) => threshold,
+            None => return,
+        };
+        let down = self.controller_button_is_down(controller_id, button);
+        if !down && value >= threshold.press {
+            self.press_controller_button(which, controller_id, button, event_handler);
+        } else if down && value <= threshold.release {
+            self.release_controller_button(which, controller_id, button, event_handler);
+        }
+    }
+
     /// Retrieve next free controller number to allocate new controller to
     fn alloc_controller_id(&self) -> u32 {
         let mut i = 0u32;
@@ -626,7 +885,7 @@ where
     }
 
     /// Map controller's index from external event into controller_id
-    fn controller_idx_to_id(&self, index: u32) -> Option<u32> {
+    pub(crate) fn controller_idx_to_id(&self, index: u32) -> Option<u32> {
         self.connected_controllers
             .iter()
             .find(|ids| ids.1 == index)
@@ -778,6 +1037,75 @@ where
     }
 }
 
+/// Treats values from -dead_zone to dead_zone as 0 and scales the others to the full range.
+fn apply_dead_zone(value: f32, dead_zone: f32) -> f32 {
+    if dead_zone >= 1.0 {
+        0.0
+    } else if value < -dead_zone {
+        (value + dead_zone) / (1.0 - dead_zone)
+    } else if value > dead_zone {
+        (value - dead_zone) / (1.0 - dead_zone)
+    } else {
+        0.0
+    }
+}
+
+/// An enum of possible errors that can occur when applying a controller state snapshot.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControllerStateError {
+    /// The snapshot ended in the middle of a controller.
+    Truncated,
+    /// The snapshot marks buttons as pressed that don't exist.
+    UnknownButtons(u32),
+    /// The snapshot holds a value for an axis that doesn't exist.
+    UnknownAxis(u8),
+    /// The snapshot has the given number of bytes left over after the last controller.
+    TrailingBytes(usize),
+}
+
+impl Display for ControllerStateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match *self {
+            ControllerStateError::Truncated => write!(f, "Controller state snapshot is truncated"),
+            ControllerStateError::UnknownButtons(buttons) => {
+                write!(f, "Unknown controller buttons in snapshot: {:#x}", buttons)
+            }
+            ControllerStateError::UnknownAxis(axis) => {
+                write!(f, "Unknown controller axis in snapshot: {}", axis)
+            }
+            ControllerStateError::TrailingBytes(len) => {
+                write!(f, "{} trailing bytes after controller state snapshot", len)
+            }
+        }
+    }
+}
+
+impl Error for ControllerStateError {}
+
+/// Reads the little endian fields of a controller state snapshot.
+struct SnapshotReader<'a>(&'a [u8]);
+
+impl SnapshotReader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], ControllerStateError> {
+        if self.0.len() < len {
+            return Err(ControllerStateError::Truncated);
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, ControllerStateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, ControllerStateError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::fmt::Debug;
@@ -790,6 +1118,316 @@ mod tests {
 
     const HIDPI: f32 = 1.0;
 
+    #[test]
+    fn controller_state_snapshot_restores_state() {
+        let mut handler = InputHandler::<StringBindings>::new();
+        let mut events = EventChannel::<InputEvent<StringBindings>>::new();
+        for event in &[
+            ControllerEvent::ControllerConnected { which: 7 },
+            ControllerEvent::ControllerConnected { which: 3 },
+            ControllerEvent::ControllerButtonPressed {
+                which: 7,
+                button: ControllerButton::A,
+            },
+            ControllerEvent::ControllerButtonPressed {
+                which: 3,
+                button: ControllerButton::Start,
+            },
+            ControllerEvent::ControllerAxisMoved {
+                which: 3,
+                axis: ControllerAxis::RightTrigger,
+                value: 0.25,
+            },
+            ControllerEvent::ControllerAxisMoved {
+                which: 3,
+                axis: ControllerAxis::LeftX,
+                value: -0.5,
+            },
+        ] {
+            handler.send_controller_event(event, &mut events);
+        }
+        let snapshot = handler.serialize_controller_state();
+        // 4 bytes count, 13 bytes header per controller and 5 bytes per axis
+        assert_eq!(snapshot.len(), 4 + 2 * 13 + 2 * 5);
+
+        for event in &[
+            ControllerEvent::ControllerButtonReleased {
+                which: 7,
+                button: ControllerButton::A,
+            },
+            ControllerEvent::ControllerButtonPressed {
+                which: 7,
+                button: ControllerButton::B,
+            },
+            ControllerEvent::ControllerAxisMoved {
+                which: 3,
+                axis: ControllerAxis::LeftX,
+                value: 1.0,
+            },
+            ControllerEvent::ControllerDisconnected { which: 3 },
+        ] {
+            handler.send_controller_event(event, &mut events);
+        }
+        assert_ne!(handler.serialize_controller_state(), snapshot);
+
+        handler.apply_controller_state(&snapshot).unwrap();
+        assert_eq!(handler.serialize_controller_state(), snapshot);
+        assert!(handler.controller_button_is_down(0, ControllerButton::A));
+        assert!(!handler.controller_button_is_down(0, ControllerButton::B));
+        assert!(handler.controller_button_is_down(1, ControllerButton::Start));
+        assert!(handler.is_controller_connected(1));
+
+        // Events keep mapping onto the restored controllers
+        handler
+            .bindings
+            .insert_axis(
+                String::from("steer"),
+                Axis::Controller {
+                    controller_id: 1,
+                    axis: ControllerAxis::LeftX,
+                    invert: false,
+                    dead_zone: 0.0,
+                },
+            )
+            .unwrap();
+        assert_eq!(handler.axis_value("steer"), Some(-0.5));
+        handler.send_controller_event(
+            &ControllerEvent::ControllerAxisMoved {
+                which: 3,
+                axis: ControllerAxis::LeftX,
+                value: 0.75,
+            },
+            &mut events,
+        );
+        assert_eq!(handler.axis_value("steer"), Some(0.75));
+
+        assert_eq!(
+            handler.apply_controller_state(&snapshot[..snapshot.len() - 1]),
+            Err(ControllerStateError::Truncated)
+        );
+        assert_eq!(handler.axis_value("steer"), Some(0.75));
+    }
+
+    #[test]
+    fn controller_axis_events_carry_raw_and_processed_values() {
+        let mut handler = InputHandler::<StringBindings>::new();
+        let mut events = EventChannel::<InputEvent<StringBindings>>::new();
+        let mut reader = events.register_reader();
+        handler.set_controller_axis_dead_zone(ControllerAxis::LeftX, 0.5);
+        handler.send_controller_event(
+            &ControllerEvent::ControllerConnected { which: 3 },
+            &mut events,
+        );
+
+        let mut send_and_read_axis = |handler: &mut InputHandler<StringBindings>, axis, value| {
+            handler.send_controller_event(
+                &ControllerEvent::ControllerAxisMoved {
+                    which: 3,
+                    axis,
+                    value,
+                },
+                &mut events,
+            );
+            events
+                .read(&mut reader)
+                .filter_map(|event| match *event {
+                    InputEvent::ControllerAxisMoved { value, raw, .. } => Some((value, raw)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            send_and_read_axis(&mut handler, ControllerAxis::LeftX, 0.25),
+            vec![(0.0, 0.25)]
+        );
+        assert_eq!(
+            send_and_read_axis(&mut handler, ControllerAxis::LeftX, -0.75),
+            vec![(-0.5, -0.75)]
+        );
+        // Axes without a dead zone report the raw value as is
+        assert_eq!(
+            send_and_read_axis(&mut handler, ControllerAxis::LeftY, 0.25),
+            vec![(0.25, 0.25)]
+        );
+        handler.remove_controller_axis_dead_zone(ControllerAxis::LeftX);
+        assert_eq!(
+            send_and_read_axis(&mut handler, ControllerAxis::LeftX, 0.25),
+            vec![(0.25, 0.25)]
+        );
+        // A full dead zone zeroes every value instead of dividing by zero
+        handler.set_controller_axis_dead_zone(ControllerAxis::LeftX, 1.0);
+        assert_eq!(
+            send_and_read_axis(&mut handler, ControllerAxis::LeftX, 1.0),
+            vec![(0.0, 1.0)]
+        );
+    }
+
+    #[test]
+    fn idle_events_carry_the_controller_index() {
+        let mut handler = InputHandler::<StringBindings>::new();
+        let mut events = EventChannel::<InputEvent<StringBindings>>::new();
+        let mut reader = events.register_reader();
+        handler.send_controller_event(
+            &ControllerEvent::ControllerConnected { which: 5 },
+            &mut events,
+        );
+        handler.send_controller_event(&ControllerEvent::ControllerIdle { which: 5 }, &mut events);
+        handler.send_controller_event(&ControllerEvent::ControllerActive { which: 5 }, &mut events);
+        // Events of unknown controllers are ignored
+        handler.send_controller_event(&ControllerEvent::ControllerIdle { which: 6 }, &mut events);
+
+        let idle_events = events
+            .read(&mut reader)
+            .filter(|event| {
+                matches!(
+                    event,
+                    InputEvent::ControllerIdle { .. } | InputEvent::ControllerActive { .. }
+                )
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            idle_events,
+            vec![
+                InputEvent::ControllerIdle { which: 5 },
+                InputEvent::ControllerActive { which: 5 },
+            ]
+        );
+    }
+
+    #[test]
+    fn trigger_presses_button_past_threshold() {
+        let mut handler = InputHandler::<StringBindings>::new();
+        let mut events = EventChannel::<InputEvent<StringBindings>>::new();
+        let mut reader = events.register_reader();
+        handler.send_controller_event(
+            &ControllerEvent::ControllerConnected { which: 5 },
+            &mut events,
+        );
+        handler.set_trigger_threshold(
+            0,
+            ControllerAxis::RightTrigger,
+            TriggerThreshold {
+                press: 0.8,
+                release: 0.5,
+            },
+        );
+
+        // The button events of the controller system are ignored in favour of the threshold
+        handler.send_controller_event(
+            &ControllerEvent::ControllerButtonPressed {
+                which: 5,
+                button: ControllerButton::RightTrigger,
+            },
+            &mut events,
+        );
+
+        let mut move_trigger = |handler: &mut InputHandler<StringBindings>, value| {
+            handler.send_controller_event(
+                &ControllerEvent::ControllerAxisMoved {
+                    which: 5,
+                    axis: ControllerAxis::RightTrigger,
+                    value,
+                },
+                &mut events,
+            );
+            events
+                .read(&mut reader)
+                .filter_map(|event| match *event {
+                    ButtonPressed(button) => Some((true, button)),
+                    ButtonReleased(button) => Some((false, button)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let trigger = Button::Controller(0, ControllerButton::RightTrigger);
+
+        assert_eq!(move_trigger(&mut handler, 0.5), vec![]);
+        assert_eq!(move_trigger(&mut handler, 0.79), vec![]);
+        assert!(!handler.button_is_down(trigger));
+        assert_eq!(move_trigger(&mut handler, 0.85), vec![(true, trigger)]);
+        assert!(handler.button_is_down(trigger));
+        // Falling below the press point doesn't release the button until the release point
+        assert_eq!(move_trigger(&mut handler, 0.6), vec![]);
+        assert!(handler.button_is_down(trigger));
+        assert_eq!(move_trigger(&mut handler, 0.5), vec![(false, trigger)]);
+        assert!(!handler.button_is_down(trigger));
+    }
+
+    #[test]
+    fn dpad_emulates_controller_axis() {
+        // Drive the left stick X axis with the dpad.
+        // Press left, then right while left is still held, then release both, checking the
+        // synthetic axis events and the stored axis value along the way.
+
+        let mut handler = InputHandler::<StringBindings>::new();
+        let mut events = EventChannel::<InputEvent<StringBindings>>::new();
+        let mut reader = events.register_reader();
+        handler
+            .bindings
+            .insert_axis(
+                String::from("horizontal"),
+                Axis::Controller {
+                    controller_id: 0,
+                    axis: ControllerAxis::LeftX,
+                    invert: false,
+                    dead_zone: 0.0,
+                },
+            )
+            .unwrap();
+        handler.emulate_controller_axis(
+            ControllerButton::DPadLeft,
+            ControllerButton::DPadRight,
+            ControllerAxis::LeftX,
+        );
+        handler.send_controller_event(
+            &ControllerEvent::ControllerConnected { which: 7 },
+            &mut events,
+        );
+
+        let mut send_and_read_axis = |handler: &mut InputHandler<StringBindings>,
+                                      event: ControllerEvent| {
+            handler.send_controller_event(&event, &mut events);
+            events
+                .read(&mut reader)
+                .filter_map(|event| match *event {
+                    InputEvent::ControllerAxisMoved {
+                        which,
+                        axis: ControllerAxis::LeftX,
+                        value,
+                        ..
+                    } => Some((which, value)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let press = |button| ControllerEvent::ControllerButtonPressed { which: 7, button };
+        let release = |button| ControllerEvent::ControllerButtonReleased { which: 7, button };
+
+        assert_eq!(
+            send_and_read_axis(&mut handler, press(ControllerButton::DPadLeft)),
+            vec![(7, -1.0)]
+        );
+        assert_eq!(handler.axis_value("horizontal"), Some(-1.0));
+        assert_eq!(
+            send_and_read_axis(&mut handler, press(ControllerButton::DPadRight)),
+            vec![(7, 0.0)]
+        );
+        assert_eq!(handler.axis_value("horizontal"), Some(0.0));
+        assert_eq!(
+            send_and_read_axis(&mut handler, release(ControllerButton::DPadLeft)),
+            vec![(7, 1.0)]
+        );
+        assert_eq!(handler.axis_value("horizontal"), Some(1.0));
+        assert_eq!(
+            send_and_read_axis(&mut handler, release(ControllerButton::DPadRight)),
+            vec![(7, 0.0)]
+        );
+        assert_eq!(handler.axis_value("horizontal"), Some(0.0));
+        assert!(send_and_read_axis(&mut handler, press(ControllerButton::A)).is_empty());
+    }
+
     #[test]
     fn key_action_response() {
         // Register an action triggered by a key
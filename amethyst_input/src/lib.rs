@@ -10,7 +10,7 @@
 #![allow(clippy::new_without_default)]
 
 #[cfg(feature = "gilrs_controller")]
-pub use self::gilrs_events_system::GilrsEventsSystem;
+pub use self::gilrs_events_system::{DuplicateControllerPolicy, GilrsConfig, GilrsEventsSystem};
 #[cfg(feature = "sdl_controller")]
 pub use self::sdl_events_system::SdlEventsSystem;
 pub use self::{
@@ -18,10 +18,13 @@ pub use self::{
     bindings::{BindingError, BindingTypes, Bindings, StringBindings},
     bundle::{BindingsFileError, InputBundle},
     button::Button,
-    controller::{ControllerAxis, ControllerButton, ControllerEvent},
+    controller::{ControllerAxis, ControllerButton, ControllerEvent, TriggerThreshold},
     event::InputEvent,
-    input_handler::InputHandler,
+    feedback::{ControllerFeedback, Rumble},
+    history::{InputHistory, InputHistorySystem, InputHistorySystemDesc},
+    input_handler::{ControllerStateError, InputHandler},
     mouse::MouseAxis,
+    ordering::{TimestampedInput, TimestampedInputEvents},
     scroll_direction::ScrollDirection,
     system::{InputSystem, InputSystemDesc},
     util::{
@@ -39,8 +42,11 @@ mod bundle;
 mod button;
 mod controller;
 mod event;
+mod feedback;
+mod history;
 mod input_handler;
 mod mouse;
+mod ordering;
 mod scroll_direction;
 mod system;
 mod util;
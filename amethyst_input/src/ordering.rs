@@ -0,0 +1,231 @@
+//! Ordering of input events coming from several sources.
+use std::time::SystemTime;
+use winit::Event;
+
+use crate::controller::ControllerEvent;
+
+/// An input event waiting in the `TimestampedInputEvents` queue.
+#[derive(Debug, Clone)]
+pub enum TimestampedInput {
+    /// An event of the windowing system.
+    Window(Event),
+    /// An event of a controller.
+    Controller(ControllerEvent),
+}
+
+/// Queue of input events stamped with the time they happened at their source.
+///
+/// The `InputSystem` publishes the queued events on the `InputEvent` channel in timestamp order.
+/// This only orders the events queued here: the gilrs controller events, and window events pushed
+/// with `push_window_event`, for instance when replaying a recording.
+///
+/// Window events read from the `EventChannel<winit::Event>` are not ordered against the queued
+/// events. winit gives them no timestamp, and stamping them when read would only record when
+/// their batch was polled, after any controller event of the same frame. They are published after
+/// the queued events, in the order they were read, so a key pressed before a controller button in
+/// the same frame is still published after it.
+#[derive(Debug, Default)]
+pub struct TimestampedInputEvents {
+    events: Vec<(SystemTime, TimestampedInput)>,
+}
+
+impl TimestampedInputEvents {
+    /// Queues a window event which happened at the given time.
+    pub fn push_window_event(&mut self, time: SystemTime, event: Event) {
+        self.events.push((time, TimestampedInput::Window(event)));
+    }
+
+    /// Queues a controller event which happened at the given time.
+    pub fn push_controller_event(&mut self, time: SystemTime, event: ControllerEvent) {
+        self.events
+            .push((time, TimestampedInput::Controller(event)));
+    }
+
+    /// Returns true if no event is queued.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Returns the number of queued events.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Removes the queued events, returned in timestamp order. Events with the same timestamp keep
+    /// the order they were queued in.
+    pub(crate) fn drain_sorted(&mut self) -> Vec<(SystemTime, TimestampedInput)> {
+        let mut events = std::mem::take(&mut self.events);
+        events.sort_by_key(|&(time, _)| time);
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ControllerButton, InputEvent, InputHandler, InputSystemDesc, StringBindings};
+    use amethyst_core::{
+        ecs::{RunNow, World, WorldExt},
+        shrev::EventChannel,
+        SystemDesc,
+    };
+    use amethyst_window::ScreenDimensions;
+    use std::time::Duration;
+    use winit::{
+        DeviceId, ElementState, KeyboardInput, ModifiersState, VirtualKeyCode, WindowEvent,
+        WindowId,
+    };
+
+    fn key_press(virtual_keycode: VirtualKeyCode) -> Event {
+        Event::WindowEvent {
+            window_id: unsafe { WindowId::dummy() },
+            event: WindowEvent::KeyboardInput {
+                device_id: unsafe { DeviceId::dummy() },
+                input: KeyboardInput {
+                    scancode: 0,
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(virtual_keycode),
+                    modifiers: ModifiersState {
+                        shift: false,
+                        ctrl: false,
+                        alt: false,
+                        logo: false,
+                    },
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn events_are_published_in_timestamp_order() {
+        let mut world = World::new();
+        world.insert(EventChannel::<Event>::new());
+        world.insert(InputHandler::<StringBindings>::new());
+        world.insert(EventChannel::<InputEvent<StringBindings>>::new());
+        world.insert(ScreenDimensions::new(100, 100, 1.0));
+        let mut system = InputSystemDesc::<StringBindings>::new(None).build(&mut world);
+        for which in 0..2 {
+            world
+                .fetch_mut::<InputHandler<StringBindings>>()
+                .send_controller_event(
+                    &ControllerEvent::ControllerConnected { which },
+                    &mut world.fetch_mut(),
+                );
+        }
+        let mut reader = world
+            .fetch_mut::<EventChannel<InputEvent<StringBindings>>>()
+            .register_reader();
+
+        let start = SystemTime::now();
+        {
+            let mut timestamped = world.fetch_mut::<TimestampedInputEvents>();
+            timestamped.push_controller_event(
+                start + Duration::from_millis(20),
+                ControllerEvent::ControllerButtonPressed {
+                    which: 1,
+                    button: ControllerButton::A,
+                },
+            );
+            timestamped.push_window_event(
+                start + Duration::from_millis(10),
+                key_press(VirtualKeyCode::Space),
+            );
+            timestamped.push_controller_event(
+                start,
+                ControllerEvent::ControllerButtonPressed {
+                    which: 0,
+                    button: ControllerButton::A,
+                },
+            );
+        }
+        system.run_now(&world);
+
+        let published = world
+            .fetch::<EventChannel<InputEvent<StringBindings>>>()
+            .read(&mut reader)
+            .filter(|event| {
+                matches!(
+                    event,
+                    InputEvent::KeyPressed { .. } | InputEvent::ControllerButtonPressed { .. }
+                )
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            published,
+            vec![
+                InputEvent::ControllerButtonPressed {
+                    which: 0,
+                    button: ControllerButton::A,
+                },
+                InputEvent::KeyPressed {
+                    key_code: VirtualKeyCode::Space,
+                    scancode: 0,
+                },
+                InputEvent::ControllerButtonPressed {
+                    which: 1,
+                    button: ControllerButton::A,
+                },
+            ]
+        );
+        assert!(world.fetch::<TimestampedInputEvents>().is_empty());
+    }
+
+    #[test]
+    fn window_events_without_timestamp_follow_queued_events() {
+        let mut world = World::new();
+        world.insert(EventChannel::<Event>::new());
+        world.insert(InputHandler::<StringBindings>::new());
+        world.insert(EventChannel::<InputEvent<StringBindings>>::new());
+        world.insert(ScreenDimensions::new(100, 100, 1.0));
+        let mut system = InputSystemDesc::<StringBindings>::new(None).build(&mut world);
+        world
+            .fetch_mut::<InputHandler<StringBindings>>()
+            .send_controller_event(
+                &ControllerEvent::ControllerConnected { which: 0 },
+                &mut world.fetch_mut(),
+            );
+        let mut reader = world
+            .fetch_mut::<EventChannel<InputEvent<StringBindings>>>()
+            .register_reader();
+
+        world
+            .fetch_mut::<EventChannel<Event>>()
+            .single_write(key_press(VirtualKeyCode::Return));
+        world
+            .fetch_mut::<TimestampedInputEvents>()
+            .push_controller_event(
+                SystemTime::now(),
+                ControllerEvent::ControllerButtonPressed {
+                    which: 0,
+                    button: ControllerButton::A,
+                },
+            );
+        system.run_now(&world);
+
+        let published = world
+            .fetch::<EventChannel<InputEvent<StringBindings>>>()
+            .read(&mut reader)
+            .filter(|event| {
+                matches!(
+                    event,
+                    InputEvent::KeyPressed { .. } | InputEvent::ControllerButtonPressed { .. }
+                )
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            published,
+            vec![
+                InputEvent::ControllerButtonPressed {
+                    which: 0,
+                    button: ControllerButton::A,
+                },
+                InputEvent::KeyPressed {
+                    key_code: VirtualKeyCode::Return,
+                    scancode: 0,
+                },
+            ]
+        );
+    }
+}
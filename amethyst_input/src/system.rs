@@ -2,7 +2,9 @@
 use derive_new::new;
 use winit::Event;
 
-use crate::{BindingTypes, Bindings, InputEvent, InputHandler};
+use crate::{
+    BindingTypes, Bindings, InputEvent, InputHandler, TimestampedInput, TimestampedInputEvents,
+};
 use amethyst_core::{
     ecs::{
         prelude::{Read, ReadExpect, System, World, Write},
@@ -44,7 +46,8 @@ where
 /// Input system
 ///
 /// Will read `winit::Event` from `EventHandler<winit::Event>`, process them with `InputHandler`,
-/// and push the results in `EventHandler<InputEvent>`.
+/// and push the results in `EventHandler<InputEvent>`. Events queued in the
+/// `TimestampedInputEvents` resource are processed first, in timestamp order.
 #[derive(Debug)]
 pub struct InputSystem<T>
 where
@@ -75,21 +78,31 @@ impl<'a, T: BindingTypes> System<'a> for InputSystem<T> {
         Read<'a, EventChannel<Event>>,
         Write<'a, InputHandler<T>>,
         Write<'a, EventChannel<InputEvent<T>>>,
+        Write<'a, TimestampedInputEvents>,
         ReadExpect<'a, ScreenDimensions>,
     );
 
-    fn run(&mut self, (input, mut handler, mut output, screen_dimensions): Self::SystemData) {
+    fn run(
+        &mut self,
+        (input, mut handler, mut output, mut timestamped, screen_dimensions): Self::SystemData,
+    ) {
         #[cfg(feature = "profiler")]
         profile_scope!("input_system");
 
         handler.send_frame_begin();
+        let hidpi = screen_dimensions.hidpi_factor() as f32;
+        for (_, event) in timestamped.drain_sorted() {
+            match event {
+                TimestampedInput::Window(event) => {
+                    Self::process_event(&event, &mut *handler, &mut *output, hidpi)
+                }
+                TimestampedInput::Controller(event) => {
+                    handler.send_controller_event(&event, &mut *output)
+                }
+            }
+        }
         for event in input.read(&mut self.reader) {
-            Self::process_event(
-                event,
-                &mut *handler,
-                &mut *output,
-                screen_dimensions.hidpi_factor() as f32,
-            );
+            Self::process_event(event, &mut *handler, &mut *output, hidpi);
         }
     }
 }
@@ -2,14 +2,29 @@
 //! more utilities to make their way into this module. e.g. "Component synchronization",
 //! "Matchmaking", etc.
 
+mod address_book;
+mod channel;
+mod congestion;
+mod drop_stats;
 mod events;
+mod fragmentation;
+mod gate;
 mod message;
 mod requirements;
+mod schedule;
 mod timing;
 mod transport;
+mod wire;
 
+pub use address_book::AddressBook;
+pub use channel::{ChannelPriority, CONTROL_CHANNEL};
+pub use congestion::{CongestionLevel, CongestionThresholds, PeerStats};
+pub use drop_stats::{DropReason, DropStats};
 pub use events::NetworkSimulationEvent;
-pub use message::Message;
+pub use fragmentation::FragmentReassembler;
+pub use gate::ReceiveGate;
+pub use message::{Message, MessageError};
 pub use requirements::{DeliveryRequirement, UrgencyRequirement};
+pub use schedule::NetworkSchedule;
 pub use timing::{NetworkSimulationTime, NetworkSimulationTimeSystem};
 pub use transport::{laminar, tcp, udp, TransportResource};
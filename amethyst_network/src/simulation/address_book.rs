@@ -0,0 +1,40 @@
+//! Resource to refer to peers by name instead of by address.
+
+use std::{collections::HashMap, net::SocketAddr};
+
+/// Maps logical peer names, e.g. "server" or "relay", to their addresses, so game code doesn't
+/// have to hold on to concrete addresses. Messages can be sent to a name with
+/// `TransportResource::send_named`.
+#[derive(Debug, Default, Clone)]
+pub struct AddressBook {
+    addresses: HashMap<String, SocketAddr>,
+}
+
+impl AddressBook {
+    /// Creates a new empty `AddressBook`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maps the name to the address, returning the address it was mapped to before, if any.
+    pub fn set(&mut self, name: impl Into<String>, addr: SocketAddr) -> Option<SocketAddr> {
+        self.addresses.insert(name.into(), addr)
+    }
+
+    /// Returns the address the name is mapped to.
+    pub fn get(&self, name: &str) -> Option<SocketAddr> {
+        self.addresses.get(name).copied()
+    }
+
+    /// Removes the name from the book, returning the address it was mapped to.
+    pub fn remove(&mut self, name: &str) -> Option<SocketAddr> {
+        self.addresses.remove(name)
+    }
+
+    /// Returns an iterator over the names and their addresses.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, SocketAddr)> {
+        self.addresses
+            .iter()
+            .map(|(name, &addr)| (name.as_str(), addr))
+    }
+}
@@ -0,0 +1,59 @@
+//! Configuration for the logical channels messages are sent on. The channel of a message is the
+//! stream id of its `DeliveryRequirement`.
+
+use crate::simulation::{message::MessageError, requirements::DeliveryRequirement};
+use std::collections::HashMap;
+
+/// Priority level assigned to messages whose channel has no configured priority.
+const DEFAULT_CHANNEL_PRIORITY: u8 = 0;
+
+/// Channel reserved for the control messages of the transports, e.g. kicks. Control messages are
+/// flagged in the header of every message so they never reach the application, and laminar sends
+/// them on this stream so they are ordered independently of the application streams. Application
+/// messages sent on it are rejected with `MessageError::ReservedChannel`.
+pub const CONTROL_CHANNEL: u8 = u8::MAX;
+
+/// Rejects application messages on the reserved control channel.
+pub(crate) fn check_channel(delivery: DeliveryRequirement) -> Result<(), MessageError> {
+    if delivery.channel() == Some(CONTROL_CHANNEL) {
+        Err(MessageError::ReservedChannel(delivery))
+    } else {
+        Ok(())
+    }
+}
+
+/// Maps channel ids to priority levels. When the messages to send are drained, messages on higher
+/// priority channels are sent first, regardless of the order they were queued in. Messages with
+/// the same priority keep their queue order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChannelPriority {
+    priorities: HashMap<u8, u8>,
+}
+
+impl ChannelPriority {
+    /// Creates a new `ChannelPriority` where every channel has the default priority.
+    pub fn new() -> Self {
+        Self {
+            priorities: HashMap::new(),
+        }
+    }
+
+    /// Sets the priority of the given channel and returns `self` for chaining.
+    pub fn with_priority(mut self, channel: u8, priority: u8) -> Self {
+        self.set_priority(channel, priority);
+        self
+    }
+
+    /// Sets the priority of the given channel. Higher values are sent first.
+    pub fn set_priority(&mut self, channel: u8, priority: u8) {
+        self.priorities.insert(channel, priority);
+    }
+
+    /// Returns the priority of the given channel. Messages without a channel and channels without a
+    /// configured priority get the lowest priority.
+    pub fn priority(&self, channel: Option<u8>) -> u8 {
+        channel
+            .and_then(|channel| self.priorities.get(&channel).copied())
+            .unwrap_or(DEFAULT_CHANNEL_PRIORITY)
+    }
+}
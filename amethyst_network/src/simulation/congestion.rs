@@ -0,0 +1,134 @@
+//! Per-peer connection statistics and the congestion level derived from them.
+//!
+//! The transport doesn't measure the round-trip time and packet loss of its peers: they are
+//! reported by the game, e.g. from its own ping messages, and stay at zero otherwise.
+
+use std::time::Duration;
+
+/// Connection statistics of a single peer.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PeerStats {
+    /// Round-trip time reported by the game.
+    pub rtt: Duration,
+    /// Number of messages still held in the `TransportResource` for the peer after the send
+    /// system ran, e.g. throttled or delayed ones. Messages queued inside the transport itself
+    /// aren't counted.
+    pub queue_depth: usize,
+    /// Ratio of lost packets in 0.0-1.0 reported by the game.
+    pub packet_loss: f32,
+}
+
+/// Values of each metric from which a peer is considered `Fair` or `Poor`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CongestionThresholds {
+    /// Round-trip time from which a peer is considered at least `Fair`.
+    pub fair_rtt: Duration,
+    /// Round-trip time from which a peer is considered `Poor`.
+    pub poor_rtt: Duration,
+    /// Packet loss from which a peer is considered at least `Fair`.
+    pub fair_packet_loss: f32,
+    /// Packet loss from which a peer is considered `Poor`.
+    pub poor_packet_loss: f32,
+    /// Number of queued messages from which a peer is considered at least `Fair`.
+    pub fair_queue_depth: usize,
+    /// Number of queued messages from which a peer is considered `Poor`.
+    pub poor_queue_depth: usize,
+}
+
+impl Default for CongestionThresholds {
+    fn default() -> Self {
+        Self {
+            fair_rtt: Duration::from_millis(100),
+            poor_rtt: Duration::from_millis(250),
+            fair_packet_loss: 0.02,
+            poor_packet_loss: 0.1,
+            fair_queue_depth: 16,
+            poor_queue_depth: 64,
+        }
+    }
+}
+
+/// Coarse congestion level of the connection to a peer, meant to throttle what is sent to it,
+/// e.g. sending snapshots less often to a `Poor` peer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CongestionLevel {
+    /// The connection keeps up with what is sent.
+    Good,
+    /// The connection shows signs of congestion.
+    Fair,
+    /// The connection is congested.
+    Poor,
+}
+
+impl CongestionLevel {
+    /// Returns the congestion level of the given stats, which is the worst level of any metric.
+    pub fn from_stats(stats: &PeerStats, thresholds: &CongestionThresholds) -> Self {
+        if stats.rtt >= thresholds.poor_rtt
+            || stats.packet_loss >= thresholds.poor_packet_loss
+            || stats.queue_depth >= thresholds.poor_queue_depth
+        {
+            CongestionLevel::Poor
+        } else if stats.rtt >= thresholds.fair_rtt
+            || stats.packet_loss >= thresholds.fair_packet_loss
+            || stats.queue_depth >= thresholds.fair_queue_depth
+        {
+            CongestionLevel::Fair
+        } else {
+            CongestionLevel::Good
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_worst_metric_determines_level() {
+        let thresholds = CongestionThresholds::default();
+        let good = PeerStats {
+            rtt: Duration::from_millis(30),
+            queue_depth: 2,
+            packet_loss: 0.0,
+        };
+        assert_eq!(
+            CongestionLevel::from_stats(&good, &thresholds),
+            CongestionLevel::Good
+        );
+
+        let fair = PeerStats {
+            queue_depth: thresholds.fair_queue_depth,
+            ..good
+        };
+        assert_eq!(
+            CongestionLevel::from_stats(&fair, &thresholds),
+            CongestionLevel::Fair
+        );
+
+        let poor = PeerStats {
+            packet_loss: 0.2,
+            ..fair
+        };
+        assert_eq!(
+            CongestionLevel::from_stats(&poor, &thresholds),
+            CongestionLevel::Poor
+        );
+    }
+
+    #[test]
+    fn test_thresholds_are_configurable() {
+        let stats = PeerStats {
+            rtt: Duration::from_millis(30),
+            ..PeerStats::default()
+        };
+        let thresholds = CongestionThresholds {
+            fair_rtt: Duration::from_millis(10),
+            poor_rtt: Duration::from_millis(20),
+            ..CongestionThresholds::default()
+        };
+        assert_eq!(
+            CongestionLevel::from_stats(&stats, &thresholds),
+            CongestionLevel::Poor
+        );
+    }
+}
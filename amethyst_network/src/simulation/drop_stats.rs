@@ -0,0 +1,73 @@
+//! Counters of the messages dropped by the send systems.
+use std::{collections::HashMap, fmt};
+
+/// Why a message queued in the `TransportResource` was dropped instead of sent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DropReason {
+    /// The socket failed to send the message. A `NetworkSimulationEvent::SendError` is emitted
+    /// along with it.
+    SendFailed,
+    /// The send queue of the stream was full.
+    QueueFull,
+    /// A newer message on the same sequenced channel replaced it before it was written.
+    Superseded,
+    /// There was no connection to the destination of the message.
+    NotConnected,
+}
+
+impl fmt::Display for DropReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match *self {
+            DropReason::SendFailed => "send failure",
+            DropReason::QueueFull => "queue full",
+            DropReason::Superseded => "superseded",
+            DropReason::NotConnected => "not connected",
+        };
+        f.write_str(reason)
+    }
+}
+
+/// Resource counting the messages dropped by the send systems, by reason.
+#[derive(Clone, Debug, Default)]
+pub struct DropStats {
+    counts: HashMap<DropReason, u64>,
+}
+
+impl DropStats {
+    /// Creates a new `DropStats` without any drop counted.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts a message dropped for the given reason.
+    pub fn record(&mut self, reason: DropReason) {
+        self.record_many(reason, 1);
+    }
+
+    /// Counts several messages dropped for the given reason.
+    pub fn record_many(&mut self, reason: DropReason, count: u64) {
+        if count > 0 {
+            *self.counts.entry(reason).or_insert(0) += count;
+        }
+    }
+
+    /// Returns the number of messages dropped for the given reason.
+    pub fn count(&self, reason: DropReason) -> u64 {
+        self.counts.get(&reason).copied().unwrap_or(0)
+    }
+
+    /// Returns the number of messages dropped for any reason.
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /// Returns the reasons messages were dropped for along with their counts.
+    pub fn iter(&self) -> impl Iterator<Item = (DropReason, u64)> + '_ {
+        self.counts.iter().map(|(&reason, &count)| (reason, count))
+    }
+
+    /// Resets every counter to zero.
+    pub fn reset(&mut self) {
+        self.counts.clear();
+    }
+}
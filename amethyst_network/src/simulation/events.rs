@@ -5,8 +5,9 @@ use std::{io, net::SocketAddr};
 /// Events which can be received from the network.
 #[derive(Debug)]
 pub enum NetworkSimulationEvent {
-    // A message was received from a remote client
-    Message(SocketAddr, Bytes),
+    // A message was received from a remote client, along with its correlation id if it is a request
+    // or a response, see `TransportResource::request`.
+    Message(SocketAddr, Bytes, Option<u64>),
     // A new host has connected to us
     Connect(SocketAddr),
     // A host has disconnected from us
@@ -17,4 +18,29 @@ pub enum NetworkSimulationEvent {
     SendError(io::Error, Message),
     // An error occurred while managing connections.
     ConnectionError(io::Error, Option<SocketAddr>),
+    // A raw datagram was received outside of any virtual connection.
+    Datagram {
+        addr: SocketAddr,
+        bytes: Bytes,
+    },
+    // A host that timed out reconnected and presented the token of its prior session.
+    SessionResumed {
+        addr: SocketAddr,
+        token: u64,
+    },
+    // The fragments of a message stopped arriving and its partial content was discarded.
+    ReassemblyTimeout {
+        addr: SocketAddr,
+        msg_id: u32,
+    },
+    // The peer at the given address kicked us for the given reason.
+    Kicked {
+        addr: SocketAddr,
+        reason: String,
+    },
+    // The listener was moved from the old address, if any, to the new one.
+    ListenerRebound {
+        old: Option<SocketAddr>,
+        new: SocketAddr,
+    },
 }
@@ -0,0 +1,292 @@
+//! Transport agnostic fragmentation of large messages. A fragmented message is sent as several
+//! messages, each carrying its position in the message header, which are put back together on the
+//! receiving end by the `FragmentReassembler`.
+
+use crate::simulation::{events::NetworkSimulationEvent, message::MessageError};
+use amethyst_core::shrev::EventChannel;
+use bytes::{BufMut, Bytes, BytesMut};
+use log::warn;
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+/// Default number of partial messages buffered per peer.
+const DEFAULT_MAX_PARTIAL_MESSAGES: usize = 8;
+
+/// Position of a fragment in its message, carried in the message header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Fragment {
+    pub msg_id: u32,
+    pub index: u16,
+    pub count: u16,
+}
+
+/// Splits the payload into fragments of at most `fragment_size` bytes each, along with their
+/// position in the message. Fails if the fragment size is zero or the payload would need more than
+/// `u16::MAX` fragments.
+pub(crate) fn fragment(
+    msg_id: u32,
+    payload: &[u8],
+    fragment_size: usize,
+) -> Result<Vec<(Fragment, Bytes)>, MessageError> {
+    if fragment_size == 0 {
+        return Err(MessageError::ZeroFragmentSize);
+    }
+    let count = payload.len().div_ceil(fragment_size).max(1);
+    if count > usize::from(u16::MAX) {
+        return Err(MessageError::TooManyFragments {
+            len: payload.len(),
+            fragment_size,
+        });
+    }
+
+    Ok((0..count)
+        .map(|index| {
+            let start = index * fragment_size;
+            let end = (start + fragment_size).min(payload.len());
+            let fragment = Fragment {
+                msg_id,
+                index: index as u16,
+                count: count as u16,
+            };
+            (fragment, Bytes::copy_from_slice(&payload[start..end]))
+        })
+        .collect())
+}
+
+/// Fragments received so far for a message.
+#[derive(Debug)]
+struct PartialMessage {
+    count: u16,
+    fragments: HashMap<u16, Bytes>,
+    last_received_at: Instant,
+}
+
+/// Resource used by the receive systems to reassemble fragmented messages.
+///
+/// Reassembly is disabled by default, in which case every message is delivered as is. Once
+/// enabled, fragments are buffered until all of their message arrived. A partial message which
+/// doesn't receive any fragment for the reassembly timeout is discarded and a
+/// `NetworkSimulationEvent::ReassemblyTimeout` is emitted. At most `max_partial_messages` partial
+/// messages are buffered per peer, the fragments starting any further message are dropped.
+#[derive(Debug)]
+pub struct FragmentReassembler {
+    reassembly_timeout: Option<Duration>,
+    max_partial_messages: usize,
+    partial: HashMap<(SocketAddr, u32), PartialMessage>,
+}
+
+impl Default for FragmentReassembler {
+    fn default() -> Self {
+        Self {
+            reassembly_timeout: None,
+            max_partial_messages: DEFAULT_MAX_PARTIAL_MESSAGES,
+            partial: HashMap::new(),
+        }
+    }
+}
+
+impl FragmentReassembler {
+    /// Creates a new `FragmentReassembler` discarding partial messages after the given timeout.
+    pub fn new(reassembly_timeout: Duration) -> Self {
+        Self {
+            reassembly_timeout: Some(reassembly_timeout),
+            ..Self::default()
+        }
+    }
+
+    /// Returns the maximum number of partial messages buffered per peer.
+    pub fn max_partial_messages(&self) -> usize {
+        self.max_partial_messages
+    }
+
+    /// Sets the maximum number of partial messages buffered per peer.
+    pub fn set_max_partial_messages(&mut self, max_partial_messages: usize) {
+        self.max_partial_messages = max_partial_messages;
+    }
+
+    /// Returns the reassembly timeout, or `None` if reassembly is disabled.
+    pub fn reassembly_timeout(&self) -> Option<Duration> {
+        self.reassembly_timeout
+    }
+
+    /// Sets the reassembly timeout. Setting `None` disables reassembly and discards the partial
+    /// messages.
+    pub fn set_reassembly_timeout(&mut self, reassembly_timeout: Option<Duration>) {
+        self.reassembly_timeout = reassembly_timeout;
+        if reassembly_timeout.is_none() {
+            self.partial.clear();
+        }
+    }
+
+    /// Returns the number of messages waiting for fragments.
+    pub fn partial_messages(&self) -> usize {
+        self.partial.len()
+    }
+
+    /// Returns the payload to deliver, if any. Payloads which aren't fragments are returned as is,
+    /// fragments are buffered until their message is complete.
+    pub(crate) fn reassemble(
+        &mut self,
+        addr: SocketAddr,
+        fragment: Option<Fragment>,
+        payload: Bytes,
+        now: Instant,
+    ) -> Option<Bytes> {
+        let Fragment {
+            msg_id,
+            index,
+            count,
+        } = match fragment {
+            Some(fragment) if self.reassembly_timeout.is_some() => fragment,
+            _ => return Some(payload),
+        };
+        if index >= count {
+            warn!(
+                "Dropping fragment {} of message {} from {} with only {} fragments",
+                index, msg_id, addr, count
+            );
+            return None;
+        }
+
+        if !self.partial.contains_key(&(addr, msg_id)) {
+            let partial_messages = self
+                .partial
+                .keys()
+                .filter(|(partial_addr, _)| *partial_addr == addr)
+                .count();
+            if partial_messages >= self.max_partial_messages {
+                warn!(
+                    "Dropping fragment of message {} from {} with {} partial messages already",
+                    msg_id, addr, partial_messages
+                );
+                return None;
+            }
+        }
+        let partial = self
+            .partial
+            .entry((addr, msg_id))
+            .or_insert_with(|| PartialMessage {
+                count,
+                fragments: HashMap::new(),
+                last_received_at: now,
+            });
+        if partial.count != count {
+            warn!(
+                "Dropping fragment of message {} from {} with a mismatching fragment count",
+                msg_id, addr
+            );
+            return None;
+        }
+        partial.last_received_at = now;
+        partial.fragments.entry(index).or_insert(payload);
+        if partial.fragments.len() < usize::from(count) {
+            return None;
+        }
+
+        let mut partial = self.partial.remove(&(addr, msg_id))?;
+        let mut message = BytesMut::new();
+        for index in 0..count {
+            message.put_slice(&partial.fragments.remove(&index)?);
+        }
+        Some(message.freeze())
+    }
+
+    /// Discards the partial messages which timed out and emits a `ReassemblyTimeout` for each.
+    pub(crate) fn sweep(
+        &mut self,
+        now: Instant,
+        channel: &mut EventChannel<NetworkSimulationEvent>,
+    ) {
+        let reassembly_timeout = match self.reassembly_timeout {
+            Some(timeout) => timeout,
+            None => return,
+        };
+        let mut timed_out = Vec::new();
+        self.partial.retain(|&(addr, msg_id), partial| {
+            if now.duration_since(partial.last_received_at) >= reassembly_timeout {
+                timed_out.push(NetworkSimulationEvent::ReassemblyTimeout { addr, msg_id });
+                false
+            } else {
+                true
+            }
+        });
+        channel.iter_write(timed_out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fragments_are_reassembled_in_any_order() {
+        let mut reassembler = FragmentReassembler::new(Duration::from_secs(1));
+        let addr = "127.0.0.1:3000".parse().unwrap();
+        let payload = (0..=255u8).collect::<Vec<_>>();
+        let now = Instant::now();
+
+        let mut fragments = fragment(7, &payload, 100).unwrap();
+        assert_eq!(fragments.len(), 3);
+        fragments.reverse();
+
+        let mut reassemble = |(header, payload): &(Fragment, Bytes)| {
+            reassembler.reassemble(addr, Some(*header), payload.clone(), now)
+        };
+        assert_eq!(reassemble(&fragments[0]), None);
+        assert_eq!(reassemble(&fragments[1]), None);
+        assert_eq!(reassemble(&fragments[2]), Some(Bytes::from(payload)));
+        assert_eq!(reassembler.partial_messages(), 0);
+    }
+
+    #[test]
+    fn test_disabled_reassembler_passes_fragments_through() {
+        let mut reassembler = FragmentReassembler::default();
+        let addr = "127.0.0.1:3000".parse().unwrap();
+        let fragments = fragment(7, b"test", 2).unwrap();
+        let (header, payload) = fragments[0].clone();
+
+        assert_eq!(
+            reassembler.reassemble(addr, Some(header), payload.clone(), Instant::now()),
+            Some(payload)
+        );
+    }
+
+    #[test]
+    fn test_partial_messages_per_peer_are_capped() {
+        let mut reassembler = FragmentReassembler::new(Duration::from_secs(1));
+        reassembler.set_max_partial_messages(2);
+        let addr = "127.0.0.1:3000".parse().unwrap();
+        let other = "127.0.0.1:3001".parse().unwrap();
+        let now = Instant::now();
+
+        let first_fragment = |msg_id| fragment(msg_id, &[0; 4], 2).unwrap().remove(0);
+        for msg_id in 0..3 {
+            let (header, payload) = first_fragment(msg_id);
+            assert_eq!(
+                reassembler.reassemble(addr, Some(header), payload, now),
+                None
+            );
+        }
+        assert_eq!(reassembler.partial_messages(), 2);
+
+        // Other peers have their own budget
+        let (header, payload) = first_fragment(0);
+        reassembler.reassemble(other, Some(header), payload, now);
+        assert_eq!(reassembler.partial_messages(), 3);
+    }
+
+    #[test]
+    fn test_invalid_fragment_sizes_are_rejected() {
+        assert_eq!(fragment(0, b"test", 0), Err(MessageError::ZeroFragmentSize));
+        assert_eq!(
+            fragment(0, &[0; 70_000], 1),
+            Err(MessageError::TooManyFragments {
+                len: 70_000,
+                fragment_size: 1
+            })
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! Resource to hold back received messages until the game is ready to process them.
+
+use crate::simulation::events::NetworkSimulationEvent;
+use amethyst_core::shrev::EventChannel;
+use bytes::Bytes;
+use std::{collections::VecDeque, net::SocketAddr};
+
+/// Resource consulted by the receive systems. While `hold` is set, e.g. during level loading,
+/// sockets keep being read so their buffers don't fill up, but the received messages are buffered
+/// instead of being written to the event channel. Once `hold` is cleared, the buffered messages
+/// are delivered in the order they were received, before any newer message.
+///
+/// Only `NetworkSimulationEvent::Message` events are held back; connection events and errors are
+/// always delivered right away.
+#[derive(Debug, Default)]
+pub struct ReceiveGate {
+    /// Whether received messages should be held back.
+    pub hold: bool,
+    held: VecDeque<(SocketAddr, Bytes, Option<u64>)>,
+}
+
+impl ReceiveGate {
+    /// Creates a new open `ReceiveGate`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of messages currently held back.
+    pub fn held_messages(&self) -> usize {
+        self.held.len()
+    }
+
+    /// Writes the held back messages to the event channel if the gate is no longer holding.
+    pub(crate) fn release(&mut self, channel: &mut EventChannel<NetworkSimulationEvent>) {
+        if !self.hold {
+            channel.iter_write(self.held.drain(..).map(|(addr, payload, correlation_id)| {
+                NetworkSimulationEvent::Message(addr, payload, correlation_id)
+            }));
+        }
+    }
+
+    /// Writes a received message to the event channel, or holds it back.
+    pub(crate) fn write_message(
+        &mut self,
+        addr: SocketAddr,
+        payload: Bytes,
+        correlation_id: Option<u64>,
+        channel: &mut EventChannel<NetworkSimulationEvent>,
+    ) {
+        if self.hold || !self.held.is_empty() {
+            self.held.push_back((addr, payload, correlation_id));
+            self.release(channel);
+        } else {
+            channel.single_write(NetworkSimulationEvent::Message(
+                addr,
+                payload,
+                correlation_id,
+            ));
+        }
+    }
+}
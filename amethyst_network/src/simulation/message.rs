@@ -1,10 +1,17 @@
-use super::requirements::{DeliveryRequirement, UrgencyRequirement};
+use super::{
+    channel::CONTROL_CHANNEL,
+    fragmentation::Fragment,
+    requirements::{DeliveryRequirement, UrgencyRequirement},
+    wire,
+};
 use bytes::Bytes;
-use std::net::SocketAddr;
+use std::{error::Error, fmt, net::SocketAddr, time::Instant};
 
 /// Structure used to hold message payloads before they are consumed and sent by an underlying
 /// NetworkSystem.
-#[derive(Debug, PartialEq, Eq)]
+///
+/// Messages compare equal regardless of when they were enqueued.
+#[derive(Debug)]
 pub struct Message {
     /// The destination to send the message.
     pub destination: SocketAddr,
@@ -14,6 +21,12 @@ pub struct Message {
     pub delivery: DeliveryRequirement,
     /// The requirement around when this message should be sent.
     pub urgency: UrgencyRequirement,
+    /// The moment this message was enqueued to be sent.
+    pub enqueued_at: Instant,
+    /// The id matching a response to its request, sent in the message header.
+    pub correlation_id: Option<u64>,
+    /// The position of the message in a fragmented message, sent in the message header.
+    pub(crate) fragment: Option<Fragment>,
 }
 
 impl Message {
@@ -29,6 +42,69 @@ impl Message {
             payload: Bytes::copy_from_slice(payload),
             delivery,
             urgency,
+            enqueued_at: Instant::now(),
+            correlation_id: None,
+            fragment: None,
         }
     }
+
+    /// Returns the header of the transports for this message.
+    pub(crate) fn header(&self) -> wire::Header {
+        wire::Header {
+            correlation_id: self.correlation_id,
+            fragment: self.fragment,
+        }
+    }
+
+    /// Returns the bytes to send, preceded by the header of the transports.
+    pub(crate) fn wire_payload(&self) -> Vec<u8> {
+        wire::encode(&self.header(), &self.payload)
+    }
+}
+
+impl PartialEq for Message {
+    fn eq(&self, other: &Self) -> bool {
+        self.destination == other.destination
+            && self.payload == other.payload
+            && self.delivery == other.delivery
+            && self.urgency == other.urgency
+            && self.correlation_id == other.correlation_id
+            && self.fragment == other.fragment
+    }
 }
+
+impl Eq for Message {}
+
+/// Error returned when a message can't be queued.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageError {
+    /// The message was sent on the reserved `CONTROL_CHANNEL` with the given requirement.
+    ReservedChannel(DeliveryRequirement),
+    /// The message was fragmented with a fragment size of zero.
+    ZeroFragmentSize,
+    /// The message of `len` bytes would need more than `u16::MAX` fragments of `fragment_size`
+    /// bytes.
+    TooManyFragments { len: usize, fragment_size: usize },
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageError::ReservedChannel(delivery) => write!(
+                f,
+                "Channel {} is reserved for transport control messages, got {:?}",
+                CONTROL_CHANNEL, delivery
+            ),
+            MessageError::ZeroFragmentSize => write!(f, "Fragment size must be greater than 0"),
+            MessageError::TooManyFragments { len, fragment_size } => write!(
+                f,
+                "Payload of {} bytes can't be split in at most {} fragments of {} bytes",
+                len,
+                u16::MAX,
+                fragment_size
+            ),
+        }
+    }
+}
+
+impl Error for MessageError {}
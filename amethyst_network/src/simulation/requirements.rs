@@ -28,6 +28,18 @@ pub enum DeliveryRequirement {
     Default,
 }
 
+impl DeliveryRequirement {
+    /// Returns the channel of the requirement, which is its stream id if it has one.
+    pub fn channel(&self) -> Option<u8> {
+        match *self {
+            DeliveryRequirement::UnreliableSequenced(stream_id)
+            | DeliveryRequirement::ReliableSequenced(stream_id)
+            | DeliveryRequirement::ReliableOrdered(stream_id) => stream_id,
+            _ => None,
+        }
+    }
+}
+
 /// Specification of urgency of the sending of a message. Typically we'll want to send messages
 /// on simulation tick but the option to send messages immediately is available.
 #[derive(Copy, Clone, Debug, PartialOrd, PartialEq, Eq)]
@@ -0,0 +1,56 @@
+//! Record of the network systems registered by the transport bundles.
+use amethyst_core::ecs::{DispatcherBuilder, System};
+use std::fmt;
+
+/// Resource listing the network systems added to the dispatcher by the transport bundles, in the
+/// order they were added along with the systems each of them runs after.
+///
+/// Useful to check where the network systems sit in the schedule when debugging timing issues,
+/// e.g. `log::debug!("{}", *world.fetch::<NetworkSchedule>())`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct NetworkSchedule {
+    systems: Vec<(&'static str, Vec<&'static str>)>,
+}
+
+impl NetworkSchedule {
+    /// Returns the name and dependencies of each network system, in registration order.
+    pub fn systems(&self) -> impl Iterator<Item = (&'static str, &[&'static str])> {
+        self.systems
+            .iter()
+            .map(|(name, dependencies)| (*name, &dependencies[..]))
+    }
+
+    /// Returns the names of the network systems, in registration order.
+    pub fn system_names(&self) -> Vec<&'static str> {
+        self.systems.iter().map(|(name, _)| *name).collect()
+    }
+
+    /// Adds the system to the dispatcher and records it in the schedule.
+    pub(crate) fn add<'a, S>(
+        &mut self,
+        builder: &mut DispatcherBuilder<'a, '_>,
+        system: S,
+        name: &'static str,
+        dependencies: &[&'static str],
+    ) where
+        S: for<'c> System<'c> + Send + 'a,
+    {
+        builder.add(system, name, dependencies);
+        self.systems.push((name, dependencies.to_vec()));
+    }
+}
+
+impl fmt::Display for NetworkSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, (name, dependencies)) in self.systems.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}. {}", index + 1, name)?;
+            if !dependencies.is_empty() {
+                write!(f, " (after {})", dependencies.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -32,6 +32,8 @@ pub struct NetworkSimulationTime {
     frame_number: u32,
     /// Accumulated duration since last simulation frame
     elapsed_duration: Duration,
+    /// Number of simulation frames per second
+    sim_frame_rate: u32,
     /// Duration per frame
     per_frame_duration: Duration,
     /// Determines how often we send messages. i.e. "Every N frames" where N is message_send_rate
@@ -97,6 +99,16 @@ impl NetworkSimulationTime {
         self.per_frame_duration
     }
 
+    /// Returns the rate at which the network simulation progresses in hertz (frames/second).
+    pub fn sim_frame_rate(&self) -> u32 {
+        self.sim_frame_rate
+    }
+
+    /// Returns the number of seconds between each simulation frame.
+    pub fn seconds_per_sim_frame(&self) -> f32 {
+        self.per_frame_duration.as_secs_f32()
+    }
+
     /// Returns the rate at which messages should be sent over the network.
     /// i.e. 'every N frames' where N is `message_send_rate`.
     pub fn message_send_rate(&self) -> u8 {
@@ -111,6 +123,7 @@ impl NetworkSimulationTime {
 
     /// Sets the rate at which the network simulation progresses. Specified in hertz (frames/second).
     pub fn set_sim_frame_rate(&mut self, new_rate: u32) {
+        self.sim_frame_rate = new_rate;
         self.per_frame_duration = Duration::from_secs(1) / new_rate;
     }
 
@@ -126,6 +139,7 @@ impl Default for NetworkSimulationTime {
             frame_number: 0,
             elapsed_duration: Duration::from_secs(0),
             // Default to 30 frames / second
+            sim_frame_rate: DEFAULT_SIM_FRAME_RATE,
             per_frame_duration: Duration::from_secs(1) / DEFAULT_SIM_FRAME_RATE,
             // Default to sending a message with every simulation frame
             message_send_rate: 1,
@@ -151,6 +165,16 @@ mod tests {
         assert_eq!(time.elapsed_duration(), Duration::from_millis(0));
     }
 
+    #[test]
+    fn test_sim_frame_rate_reflects_runtime_changes() {
+        let mut time = NetworkSimulationTime::default();
+        assert_eq!(time.sim_frame_rate(), DEFAULT_SIM_FRAME_RATE);
+
+        time.set_sim_frame_rate(60);
+        assert_eq!(time.sim_frame_rate(), 60);
+        assert!((time.seconds_per_sim_frame() - 1.0 / 60.0).abs() < f32::EPSILON);
+    }
+
     #[test]
     fn test_message_send_rate_should_send_every_2_frames() {
         let mut time = NetworkSimulationTime::default();
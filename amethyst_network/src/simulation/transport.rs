@@ -12,10 +12,15 @@ const NETWORK_RECV_SYSTEM_NAME: &str = "network_recv";
 const NETWORK_POLL_SYSTEM_NAME: &str = "network_poll";
 
 use crate::simulation::{
-    message::Message,
+    address_book::AddressBook,
+    channel::{check_channel, ChannelPriority},
+    fragmentation,
+    message::{Message, MessageError},
     requirements::{DeliveryRequirement, UrgencyRequirement},
 };
-use std::{collections::VecDeque, net::SocketAddr};
+use bytes::Bytes;
+use log::warn;
+use std::{cmp::Reverse, collections::VecDeque, net::SocketAddr, time::Duration};
 
 /// Resource serving as the owner of the queue of messages to be sent. This resource also serves
 /// as the interface for other systems to send messages.
@@ -24,6 +29,12 @@ pub struct TransportResource {
     frame_budget_bytes: i32,
     latency_nanos: i64,
     packet_loss: f32,
+    channel_priority: ChannelPriority,
+    simulated_latency: Option<Duration>,
+    next_fragmented_msg_id: u32,
+    streamed: VecDeque<VecDeque<Message>>,
+    streamed_chunks_per_tick: usize,
+    next_correlation_id: u64,
 }
 
 impl TransportResource {
@@ -34,6 +45,12 @@ impl TransportResource {
             frame_budget_bytes: 0,
             latency_nanos: 0,
             packet_loss: 0.0,
+            channel_priority: ChannelPriority::new(),
+            simulated_latency: None,
+            next_fragmented_msg_id: 0,
+            streamed: VecDeque::new(),
+            streamed_chunks_per_tick: 1,
+            next_correlation_id: 0,
         }
     }
 
@@ -77,10 +94,41 @@ impl TransportResource {
         self.packet_loss = loss;
     }
 
+    /// Returns the channel priorities used to order the messages to send.
+    pub fn channel_priority(&self) -> &ChannelPriority {
+        &self.channel_priority
+    }
+
+    /// Sets the channel priorities used to order the messages to send.
+    pub fn set_channel_priority(&mut self, channel_priority: ChannelPriority) {
+        self.channel_priority = channel_priority;
+    }
+
+    /// Returns the simulated latency applied to the messages to send, if any.
+    pub fn simulated_latency(&self) -> Option<Duration> {
+        self.simulated_latency
+    }
+
+    /// Simulates network latency by holding back every message to send until it has been enqueued
+    /// for at least the given duration. This is meant for testing under adverse conditions.
+    pub fn set_simulated_latency(&mut self, latency: Option<Duration>) {
+        self.simulated_latency = latency;
+    }
+
+    /// Returns the number of chunks of each streamed payload sent per tick.
+    pub fn streamed_chunks_per_tick(&self) -> usize {
+        self.streamed_chunks_per_tick
+    }
+
+    /// Sets the number of chunks of each streamed payload sent per tick.
+    pub fn set_streamed_chunks_per_tick(&mut self, chunks: usize) {
+        self.streamed_chunks_per_tick = chunks;
+    }
+
     /// Creates a `Message` with the default guarantees provided by the `Socket` implementation and
     /// pushes it onto the messages queue to be sent on next sim tick.
     pub fn send(&mut self, destination: SocketAddr, payload: &[u8]) {
-        self.send_with_requirements(
+        self.queue(
             destination,
             payload,
             DeliveryRequirement::Default,
@@ -91,7 +139,7 @@ impl TransportResource {
     /// Creates a `Message` with the default guarantees provided by the `Socket` implementation and
     /// Pushes it onto the messages queue to be sent immediately.
     pub fn send_immediate(&mut self, destination: SocketAddr, payload: &[u8]) {
-        self.send_with_requirements(
+        self.queue(
             destination,
             payload,
             DeliveryRequirement::Default,
@@ -99,21 +147,181 @@ impl TransportResource {
         );
     }
 
-    /// Creates and queue a `Message` with the specified guarantee.
+    /// Creates and queue a `Message` with the specified guarantee. A message on the reserved
+    /// `CONTROL_CHANNEL` is dropped with a warning, see `try_send_with_requirements`.
     pub fn send_with_requirements(
         &mut self,
         destination: SocketAddr,
         payload: &[u8],
         delivery: DeliveryRequirement,
         timing: UrgencyRequirement,
+    ) {
+        if let Err(e) = self.try_send_with_requirements(destination, payload, delivery, timing) {
+            warn!("Dropping message to {}: {}", destination, e);
+        }
+    }
+
+    /// Creates and queue a `Message` with the specified guarantee. Fails without queuing anything
+    /// if the guarantee uses the reserved `CONTROL_CHANNEL`.
+    pub fn try_send_with_requirements(
+        &mut self,
+        destination: SocketAddr,
+        payload: &[u8],
+        delivery: DeliveryRequirement,
+        timing: UrgencyRequirement,
+    ) -> Result<(), MessageError> {
+        check_channel(delivery)?;
+        self.queue(destination, payload, delivery, timing);
+        Ok(())
+    }
+
+    fn queue(
+        &mut self,
+        destination: SocketAddr,
+        payload: &[u8],
+        delivery: DeliveryRequirement,
+        timing: UrgencyRequirement,
     ) {
         let message = Message::new(destination, payload, delivery, timing);
         self.messages.push_back(message);
     }
 
-    /// Returns true if there are messages enqueued to be sent.
+    /// Splits the payload into a `Message` per fragment, carrying its position in the header.
+    fn fragment(
+        &mut self,
+        destination: SocketAddr,
+        payload: &[u8],
+        fragment_size: usize,
+        delivery: DeliveryRequirement,
+        timing: UrgencyRequirement,
+    ) -> Result<Vec<Message>, MessageError> {
+        check_channel(delivery)?;
+        let msg_id = self.next_fragmented_msg_id;
+        let fragments = fragmentation::fragment(msg_id, payload, fragment_size)?;
+        self.next_fragmented_msg_id = self.next_fragmented_msg_id.wrapping_add(1);
+        Ok(fragments
+            .into_iter()
+            .map(|(fragment, payload)| {
+                let mut message = Message::new(destination, &payload, delivery, timing);
+                message.fragment = Some(fragment);
+                message
+            })
+            .collect())
+    }
+
+    /// Resolves the peer name through the address book and queues a `Message` to its address with
+    /// the default guarantees, to be sent on next sim tick. Returns the resolved address, or `None`
+    /// if the name is unknown and nothing was queued.
+    pub fn send_named(
+        &mut self,
+        book: &AddressBook,
+        name: &str,
+        payload: &[u8],
+    ) -> Option<SocketAddr> {
+        let destination = book.get(name)?;
+        self.send(destination, payload);
+        Some(destination)
+    }
+
+    /// Resolves the peer name through the address book and queues a `Message` to its address with
+    /// the specified guarantee. Returns the resolved address, or `None` if the name is unknown and
+    /// nothing was queued. Fails if the guarantee uses the reserved `CONTROL_CHANNEL`.
+    pub fn send_named_with_requirements(
+        &mut self,
+        book: &AddressBook,
+        name: &str,
+        payload: &[u8],
+        delivery: DeliveryRequirement,
+        timing: UrgencyRequirement,
+    ) -> Result<Option<SocketAddr>, MessageError> {
+        check_channel(delivery)?;
+        let destination = book.get(name);
+        if let Some(destination) = destination {
+            self.queue(destination, payload, delivery, timing);
+        }
+        Ok(destination)
+    }
+
+    /// Queues a `Message` carrying a newly allocated correlation id, to be sent on next sim tick.
+    /// The id is carried in the message header and the receiving end gets it on the
+    /// `NetworkSimulationEvent::Message`, then echoes it with `respond` so the response can be
+    /// matched to the request. The TCP transport requires framing to send it. Fails if the
+    /// guarantee uses the reserved `CONTROL_CHANNEL`.
+    pub fn request(
+        &mut self,
+        destination: SocketAddr,
+        payload: &[u8],
+        delivery: DeliveryRequirement,
+    ) -> Result<u64, MessageError> {
+        check_channel(delivery)?;
+        let correlation_id = self.next_correlation_id;
+        self.next_correlation_id = self.next_correlation_id.wrapping_add(1);
+        self.respond(destination, correlation_id, payload, delivery)?;
+        Ok(correlation_id)
+    }
+
+    /// Queues a `Message` carrying the correlation id of the request it responds to, to be sent
+    /// on next sim tick. Fails if the guarantee uses the reserved `CONTROL_CHANNEL`.
+    pub fn respond(
+        &mut self,
+        destination: SocketAddr,
+        correlation_id: u64,
+        payload: &[u8],
+        delivery: DeliveryRequirement,
+    ) -> Result<(), MessageError> {
+        check_channel(delivery)?;
+        let mut message = Message::new(destination, payload, delivery, UrgencyRequirement::OnTick);
+        message.correlation_id = Some(correlation_id);
+        self.messages.push_back(message);
+        Ok(())
+    }
+
+    /// Splits the payload into fragments of at most `fragment_size` bytes and queues a `Message`
+    /// with the specified guarantee for each of them. The receiving end must have fragment
+    /// reassembly enabled through its `FragmentReassembler` resource, and the TCP transport
+    /// requires framing. Fails without queuing anything if the guarantee uses the reserved
+    /// `CONTROL_CHANNEL`, the fragment size is zero or the payload needs more than `u16::MAX`
+    /// fragments.
+    pub fn send_fragmented(
+        &mut self,
+        destination: SocketAddr,
+        payload: &[u8],
+        fragment_size: usize,
+        delivery: DeliveryRequirement,
+        timing: UrgencyRequirement,
+    ) -> Result<(), MessageError> {
+        let fragments = self.fragment(destination, payload, fragment_size, delivery, timing)?;
+        self.messages.extend(fragments);
+        Ok(())
+    }
+
+    /// Splits the data into fragments of at most `chunk_size` bytes which are sent over the
+    /// following ticks, `streamed_chunks_per_tick` at a time, instead of all at once. This smooths
+    /// out the bandwidth used by large transfers. The receiving end must have fragment reassembly
+    /// enabled through its `FragmentReassembler` resource, and the TCP transport requires framing.
+    /// Fails without queuing anything if the guarantee uses the reserved `CONTROL_CHANNEL`, the
+    /// chunk size is zero or the data needs more than `u16::MAX` chunks.
+    pub fn send_streamed(
+        &mut self,
+        destination: SocketAddr,
+        data: Bytes,
+        chunk_size: usize,
+        delivery: DeliveryRequirement,
+    ) -> Result<(), MessageError> {
+        let chunks = self.fragment(
+            destination,
+            &data,
+            chunk_size,
+            delivery,
+            UrgencyRequirement::OnTick,
+        )?;
+        self.streamed.push_back(chunks.into());
+        Ok(())
+    }
+
+    /// Returns true if there are messages enqueued to be sent, streamed chunks included.
     pub fn has_messages(&self) -> bool {
-        !self.messages.is_empty()
+        !self.messages.is_empty() || !self.streamed.is_empty()
     }
 
     /// Returns a reference to the owned messages.
@@ -122,14 +330,39 @@ impl TransportResource {
     }
 
     /// Returns the messages to send by returning the immediate messages or anything adhering to
-    /// the given filter.
+    /// the given filter, along with the next chunks of the streamed payloads. The messages are
+    /// ordered by the priority of their channel. Messages held back by the simulated latency are
+    /// never returned.
     pub fn drain_messages_to_send(
         &mut self,
         mut filter: impl FnMut(&mut Message) -> bool,
     ) -> Vec<Message> {
-        self.drain_messages(|message| {
-            message.urgency == UrgencyRequirement::Immediate || filter(message)
-        })
+        let simulated_latency = self.simulated_latency;
+        let mut filter = |message: &mut Message| {
+            let delayed = match simulated_latency {
+                Some(latency) => message.enqueued_at.elapsed() < latency,
+                None => false,
+            };
+            !delayed && (message.urgency == UrgencyRequirement::Immediate || filter(message))
+        };
+        let mut messages = self.drain_messages(&mut filter);
+        for chunks in self.streamed.iter_mut() {
+            for _ in 0..self.streamed_chunks_per_tick {
+                let send = match chunks.front_mut() {
+                    Some(chunk) => filter(chunk),
+                    None => false,
+                };
+                if !send {
+                    break;
+                }
+                messages.extend(chunks.pop_front());
+            }
+        }
+        self.streamed.retain(|chunks| !chunks.is_empty());
+        let channel_priority = &self.channel_priority;
+        messages
+            .sort_by_key(|message| Reverse(channel_priority.priority(message.delivery.channel())));
+        messages
     }
 
     /// Drains the messages queue and returns the drained messages. The filter allows you to drain
@@ -158,13 +391,73 @@ impl Default for TransportResource {
             frame_budget_bytes: 0,
             latency_nanos: 0,
             packet_loss: 0.0,
+            channel_priority: ChannelPriority::new(),
+            simulated_latency: None,
+            next_fragmented_msg_id: 0,
+            streamed: VecDeque::new(),
+            streamed_chunks_per_tick: 1,
+            next_correlation_id: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test_utils {
+    use super::*;
+    use crate::simulation::events::NetworkSimulationEvent;
+    use amethyst_core::{
+        ecs::{RunNow, World},
+        shrev::{EventChannel, ReaderId},
+    };
+    use std::{thread, time::Instant};
+
+    /// Enqueues a message in the sender world and dispatches the given send and receive systems
+    /// until the message is received on the other end. Returns the time elapsed since the message
+    /// was enqueued, or `None` if it wasn't received within the timeout.
+    #[allow(clippy::too_many_arguments)]
+    pub fn measure_latency<'a, S, R>(
+        sender: &'a World,
+        send_system: &mut S,
+        receiver: &'a World,
+        recv_system: &mut R,
+        reader: &mut ReaderId<NetworkSimulationEvent>,
+        destination: SocketAddr,
+        payload: &[u8],
+        timeout: Duration,
+    ) -> Option<Duration>
+    where
+        S: RunNow<'a>,
+        R: RunNow<'a>,
+    {
+        let enqueued_at = {
+            let mut transport = sender.fetch_mut::<TransportResource>();
+            transport.send(destination, payload);
+            transport.get_messages().back()?.enqueued_at
+        };
+
+        while enqueued_at.elapsed() < timeout {
+            send_system.run_now(sender);
+            recv_system.run_now(receiver);
+            let received = receiver
+                .fetch::<EventChannel<NetworkSimulationEvent>>()
+                .read(reader)
+                .any(|event| match event {
+                    NetworkSimulationEvent::Message(_, bytes, _) => bytes == payload,
+                    _ => false,
+                });
+            if received {
+                return Some(Instant::now() - enqueued_at);
+            }
+            thread::sleep(Duration::from_millis(1));
         }
+        None
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::simulation::channel::CONTROL_CHANNEL;
 
     #[test]
     fn test_send_with_default_requirements() {
@@ -266,6 +559,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_send_named_targets_resolved_address() {
+        let mut resource = create_test_resource();
+        let mut book = AddressBook::new();
+        let server_addr = "127.0.0.1:3000".parse().unwrap();
+        assert_eq!(book.set("server", server_addr), None);
+
+        assert_eq!(
+            resource.send_named(&book, "server", test_payload()),
+            Some(server_addr)
+        );
+        assert_eq!(resource.send_named(&book, "relay", test_payload()), None);
+        assert_eq!(resource.messages.len(), 1);
+        assert_eq!(resource.messages[0].destination, server_addr);
+        assert_eq!(resource.messages[0].payload, test_payload());
+
+        // Remapping the name redirects later messages
+        let new_addr = "127.0.0.1:3001".parse().unwrap();
+        assert_eq!(book.set("server", new_addr), Some(server_addr));
+        resource.send_named(&book, "server", test_payload());
+        assert_eq!(resource.messages[1].destination, new_addr);
+    }
+
     #[test]
     fn test_send_with_requirements() {
         use DeliveryRequirement::*;
@@ -292,6 +608,135 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_application_sends_on_control_channel_are_rejected() {
+        let mut resource = create_test_resource();
+        let addr = "127.0.0.1:3000".parse().unwrap();
+        let delivery = DeliveryRequirement::ReliableOrdered(Some(CONTROL_CHANNEL));
+
+        assert_eq!(
+            resource.try_send_with_requirements(
+                addr,
+                test_payload(),
+                delivery,
+                UrgencyRequirement::OnTick
+            ),
+            Err(MessageError::ReservedChannel(delivery))
+        );
+        resource.send_with_requirements(addr, test_payload(), delivery, UrgencyRequirement::OnTick);
+        assert!(resource
+            .send_streamed(addr, Bytes::from_static(b"data"), 2, delivery)
+            .is_err());
+        assert!(resource.request(addr, test_payload(), delivery).is_err());
+        assert!(!resource.has_messages());
+    }
+
+    #[test]
+    fn test_drain_sends_high_priority_channel_first() {
+        const INPUT_CHANNEL: u8 = 1;
+        const BULK_CHANNEL: u8 = 2;
+
+        let mut resource = create_test_resource();
+        resource.set_channel_priority(
+            ChannelPriority::new()
+                .with_priority(INPUT_CHANNEL, 10)
+                .with_priority(BULK_CHANNEL, 1),
+        );
+
+        let addr = "127.0.0.1:3000".parse().unwrap();
+        for payload in [b"bulk1", b"inpt1", b"bulk2", b"inpt2"].iter() {
+            let channel = if payload.starts_with(b"bulk") {
+                BULK_CHANNEL
+            } else {
+                INPUT_CHANNEL
+            };
+            resource.send_with_requirements(
+                addr,
+                &payload[..],
+                DeliveryRequirement::ReliableOrdered(Some(channel)),
+                UrgencyRequirement::OnTick,
+            );
+        }
+
+        let payloads = resource
+            .drain_messages_to_send(|_| true)
+            .into_iter()
+            .map(|message| message.payload)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            payloads,
+            vec![&b"inpt1"[..], &b"inpt2"[..], &b"bulk1"[..], &b"bulk2"[..]]
+        );
+    }
+
+    #[test]
+    fn test_messages_compare_equal_regardless_of_enqueue_time() {
+        let mut resource = create_test_resource();
+        let addr = "127.0.0.1:3000".parse().unwrap();
+        resource.send(addr, test_payload());
+        std::thread::sleep(Duration::from_millis(1));
+        resource.send(addr, test_payload());
+
+        let messages = resource.drain_messages_to_send(|_| true);
+        assert_ne!(messages[0].enqueued_at, messages[1].enqueued_at);
+        assert_eq!(messages[0], messages[1]);
+    }
+
+    #[test]
+    fn test_simulated_latency_holds_back_messages() {
+        let mut resource = create_test_resource();
+        resource.set_simulated_latency(Some(Duration::from_secs(60)));
+
+        let addr = "127.0.0.1:3000".parse().unwrap();
+        resource.send_immediate(addr, test_payload());
+        resource.send(addr, test_payload());
+
+        assert!(resource.drain_messages_to_send(|_| true).is_empty());
+        assert_eq!(resource.get_messages().len(), 2);
+
+        resource.set_simulated_latency(None);
+        assert_eq!(resource.drain_messages_to_send(|_| true).len(), 2);
+    }
+
+    #[test]
+    fn test_streamed_payload_is_spread_across_ticks() {
+        use crate::simulation::fragmentation::FragmentReassembler;
+        use std::time::Instant;
+
+        let mut resource = create_test_resource();
+        let addr = "127.0.0.1:3000".parse().unwrap();
+        let data = (0..1000).map(|i| i as u8).collect::<Bytes>();
+        resource
+            .send_streamed(
+                addr,
+                data.clone(),
+                100,
+                DeliveryRequirement::ReliableOrdered(None),
+            )
+            .unwrap();
+        assert!(resource.has_messages());
+
+        // Nothing goes out on frames where messages aren't sent
+        assert!(resource.drain_messages_to_send(|_| false).is_empty());
+
+        let mut reassembler = FragmentReassembler::new(Duration::from_secs(1));
+        let mut ticks = 0;
+        let mut reassembled = None;
+        while resource.has_messages() {
+            let messages = resource.drain_messages_to_send(|_| true);
+            assert_eq!(messages.len(), 1);
+            ticks += 1;
+            for message in messages {
+                assert_eq!(message.destination, addr);
+                assert_eq!(message.delivery, DeliveryRequirement::ReliableOrdered(None));
+                reassembled =
+                    reassembler.reassemble(addr, message.fragment, message.payload, Instant::now());
+            }
+        }
+        assert_eq!(ticks, 10);
+        assert_eq!(reassembled, Some(data));
+    }
+
     fn test_payload() -> &'static [u8] {
         b"test"
     }
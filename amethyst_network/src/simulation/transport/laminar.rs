@@ -1,13 +1,20 @@
 //! Network systems implementation backed by the Laminar network protocol.
 
 use crate::simulation::{
+    channel::CONTROL_CHANNEL,
+    congestion::{CongestionLevel, CongestionThresholds, PeerStats},
+    drop_stats::{DropReason, DropStats},
     events::NetworkSimulationEvent,
+    fragmentation::FragmentReassembler,
+    gate::ReceiveGate,
     requirements::DeliveryRequirement,
+    schedule::NetworkSchedule,
     timing::{NetworkSimulationTime, NetworkSimulationTimeSystem},
     transport::{
         TransportResource, NETWORK_POLL_SYSTEM_NAME, NETWORK_RECV_SYSTEM_NAME,
         NETWORK_SEND_SYSTEM_NAME, NETWORK_SIM_TIME_SYSTEM_NAME,
     },
+    wire::{self, Control, Received},
 };
 use amethyst_core::{
     bundle::SystemBundle,
@@ -20,16 +27,84 @@ use laminar::{Packet, SocketEvent};
 
 use bytes::Bytes;
 use log::error;
-use std::time::Instant;
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    ops::DerefMut,
+    time::{Duration, Instant},
+};
+
+/// Default time a timed out peer may take to reconnect and resume its session.
+const DEFAULT_SESSION_RESUME_WINDOW: Duration = Duration::from_secs(60);
 
 /// Use this network bundle to add the laminar transport layer to your game.
 pub struct LaminarNetworkBundle {
     socket: Option<LaminarSocket>,
+    bind: Option<(io::Result<Vec<SocketAddr>>, LaminarConfig)>,
+    datagram_socket: Option<(UdpSocket, usize)>,
+    session_resumption: bool,
+    session_resume_window: Duration,
+    max_recv_events_per_tick: Option<usize>,
 }
 
 impl LaminarNetworkBundle {
     pub fn new(socket: Option<LaminarSocket>) -> Self {
-        Self { socket }
+        Self {
+            socket,
+            bind: None,
+            datagram_socket: None,
+            session_resumption: false,
+            session_resume_window: DEFAULT_SESSION_RESUME_WINDOW,
+            max_recv_events_per_tick: None,
+        }
+    }
+
+    /// Creates a bundle binding its socket to the given address with the default config once the
+    /// bundle is built.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> Self {
+        Self::bind_with_config(addr, LaminarConfig::default())
+    }
+
+    /// Creates a bundle binding its socket to the given address with the given config once the
+    /// bundle is built. Failing to resolve or bind the address fails the build.
+    pub fn bind_with_config<A: ToSocketAddrs>(addr: A, config: LaminarConfig) -> Self {
+        let addrs = addr.to_socket_addrs().map(Iterator::collect);
+        Self {
+            bind: Some((addrs, config)),
+            ..Self::new(None)
+        }
+    }
+
+    /// Enables session resumption, see `LaminarSocketResource::set_session_resumption`.
+    pub fn with_session_resumption(mut self) -> Self {
+        self.session_resumption = true;
+        self
+    }
+
+    /// Sets how long a timed out peer may take to resume its session, see
+    /// `LaminarSocketResource::set_session_resume_window`.
+    pub fn with_session_resume_window(mut self, window: Duration) -> Self {
+        self.session_resume_window = window;
+        self
+    }
+
+    /// Caps the socket events processed per tick, see
+    /// `LaminarSocketResource::set_max_recv_events_per_tick`.
+    pub fn with_max_recv_events_per_tick(mut self, max_events: usize) -> Self {
+        self.max_recv_events_per_tick = Some(max_events);
+        self
+    }
+
+    /// Adds an auxiliary `UdpSocket` used to send and receive raw datagrams outside of laminar's
+    /// virtual connections, e.g. for LAN discovery broadcasts.
+    pub fn with_datagram_socket(
+        mut self,
+        socket: UdpSocket,
+        recv_buffer_size_bytes: usize,
+    ) -> Self {
+        self.datagram_socket = Some((socket, recv_buffer_size_bytes));
+        self
     }
 }
 
@@ -39,30 +114,49 @@ impl<'a, 'b> SystemBundle<'a, 'b> for LaminarNetworkBundle {
         world: &mut World,
         builder: &mut DispatcherBuilder<'_, '_>,
     ) -> Result<(), Error> {
-        builder.add(
+        let mut schedule = world.remove::<NetworkSchedule>().unwrap_or_default();
+        schedule.add(
+            builder,
             NetworkSimulationTimeSystem,
             NETWORK_SIM_TIME_SYSTEM_NAME,
             &[],
         );
 
-        builder.add(
+        schedule.add(
+            builder,
             LaminarNetworkSendSystem,
             NETWORK_SEND_SYSTEM_NAME,
             &[NETWORK_SIM_TIME_SYSTEM_NAME],
         );
 
-        builder.add(
+        schedule.add(
+            builder,
             LaminarNetworkPollSystem,
             NETWORK_POLL_SYSTEM_NAME,
             &[NETWORK_SEND_SYSTEM_NAME],
         );
-        builder.add(
+        schedule.add(
+            builder,
             LaminarNetworkRecvSystem,
             NETWORK_RECV_SYSTEM_NAME,
             &[NETWORK_POLL_SYSTEM_NAME],
         );
 
-        world.insert(LaminarSocketResource::new(self.socket));
+        let mut resource = match self.bind {
+            Some((addrs, config)) => {
+                let socket = LaminarSocket::bind_with_config(&addrs?[..], config.clone())?;
+                LaminarSocketResource::with_config(socket, config)
+            }
+            None => LaminarSocketResource::new(self.socket),
+        };
+        if let Some((socket, recv_buffer_size_bytes)) = self.datagram_socket {
+            resource.set_datagram_socket(socket, recv_buffer_size_bytes)?;
+        }
+        resource.set_session_resumption(self.session_resumption);
+        resource.set_session_resume_window(self.session_resume_window);
+        resource.set_max_recv_events_per_tick(self.max_recv_events_per_tick);
+        world.insert(resource);
+        world.insert(schedule);
         Ok(())
     }
 }
@@ -74,58 +168,59 @@ impl<'s> System<'s> for LaminarNetworkSendSystem {
         Write<'s, TransportResource>,
         Write<'s, LaminarSocketResource>,
         Read<'s, NetworkSimulationTime>,
+        Write<'s, DropStats>,
         Write<'s, EventChannel<NetworkSimulationEvent>>,
     );
 
-    fn run(&mut self, (mut transport, mut socket, sim_time, mut event_channel): Self::SystemData) {
-        if let Some(socket) = socket.get_mut() {
+    fn run(
+        &mut self,
+        (mut transport, mut socket, sim_time, mut drops, mut event_channel): Self::SystemData,
+    ) {
+        let resource = socket.deref_mut();
+        if let Some(ref mut socket) = resource.socket {
             let messages = transport.drain_messages_to_send(|_| sim_time.should_send_message_now());
 
             for message in messages {
+                if resource.kicked.contains(&message.destination) {
+                    drops.record(DropReason::NotConnected);
+                    continue;
+                }
+                let payload = message.wire_payload();
                 let packet = match message.delivery {
                     DeliveryRequirement::Unreliable => {
-                        Packet::unreliable(message.destination, message.payload.to_vec())
+                        Packet::unreliable(message.destination, payload)
                     }
                     DeliveryRequirement::UnreliableSequenced(stream_id) => {
-                        Packet::unreliable_sequenced(
-                            message.destination,
-                            message.payload.to_vec(),
-                            stream_id,
-                        )
+                        Packet::unreliable_sequenced(message.destination, payload, stream_id)
                     }
                     DeliveryRequirement::Reliable => {
-                        Packet::reliable_unordered(message.destination, message.payload.to_vec())
+                        Packet::reliable_unordered(message.destination, payload)
                     }
                     DeliveryRequirement::ReliableSequenced(stream_id) => {
-                        Packet::reliable_sequenced(
-                            message.destination,
-                            message.payload.to_vec(),
-                            stream_id,
-                        )
+                        Packet::reliable_sequenced(message.destination, payload, stream_id)
+                    }
+                    DeliveryRequirement::ReliableOrdered(stream_id) => {
+                        Packet::reliable_ordered(message.destination, payload, stream_id)
+                    }
+                    DeliveryRequirement::Default => {
+                        Packet::reliable_ordered(message.destination, payload, None)
                     }
-                    DeliveryRequirement::ReliableOrdered(stream_id) => Packet::reliable_ordered(
-                        message.destination,
-                        message.payload.to_vec(),
-                        stream_id,
-                    ),
-                    DeliveryRequirement::Default => Packet::reliable_ordered(
-                        message.destination,
-                        message.payload.to_vec(),
-                        None,
-                    ),
                 };
 
                 match socket.send(packet) {
                     Err(ErrorKind::IOError(e)) => {
+                        drops.record(DropReason::SendFailed);
                         event_channel.single_write(NetworkSimulationEvent::SendError(e, message));
                     }
                     Err(e) => {
+                        drops.record(DropReason::SendFailed);
                         error!("Error sending message: {:?}", e);
                     }
                     Ok(_) => {}
                 }
             }
         }
+        resource.update_queue_depths(&transport);
     }
 }
 
@@ -146,41 +241,244 @@ struct LaminarNetworkRecvSystem;
 impl<'s> System<'s> for LaminarNetworkRecvSystem {
     type SystemData = (
         Write<'s, LaminarSocketResource>,
+        Write<'s, FragmentReassembler>,
+        Write<'s, ReceiveGate>,
         Write<'s, EventChannel<NetworkSimulationEvent>>,
     );
 
-    fn run(&mut self, (mut socket, mut event_channel): Self::SystemData) {
-        if let Some(socket) = socket.get_mut() {
-            while let Some(event) = socket.recv() {
-                let event = match event {
-                    SocketEvent::Packet(packet) => NetworkSimulationEvent::Message(
-                        packet.addr(),
-                        Bytes::copy_from_slice(packet.payload()),
-                    ),
-                    SocketEvent::Connect(addr) => NetworkSimulationEvent::Connect(addr),
-                    SocketEvent::Timeout(addr) => NetworkSimulationEvent::Disconnect(addr),
+    fn run(
+        &mut self,
+        (mut socket, mut reassembler, mut gate, mut event_channel): Self::SystemData,
+    ) {
+        reassembler.sweep(Instant::now(), &mut event_channel);
+        gate.release(&mut event_channel);
+        let resource = socket.deref_mut();
+        if let Some(ref mut sessions) = resource.sessions {
+            event_channel
+                .iter_write(sessions.evict(Instant::now(), resource.session_resume_window));
+        }
+        let max_events = resource.max_recv_events_per_tick.unwrap_or(usize::MAX);
+        if let Some(ref mut socket) = resource.socket {
+            for _ in 0..max_events {
+                let event = match socket.recv() {
+                    Some(event) => event,
+                    None => break,
                 };
-                event_channel.single_write(event);
+                match event {
+                    SocketEvent::Packet(packet) => {
+                        let addr = packet.addr();
+                        if resource.kicked.contains(&addr) {
+                            continue;
+                        }
+                        let (header, payload) =
+                            match wire::decode(Bytes::copy_from_slice(packet.payload())) {
+                                Ok(Received::Message(header, payload)) => (header, payload),
+                                Ok(Received::Control(Control::SessionToken(token))) => {
+                                    if let Some(ref mut sessions) = resource.sessions {
+                                        if let Some(event) = sessions.on_token(addr, token) {
+                                            event_channel.single_write(event);
+                                        }
+                                    }
+                                    continue;
+                                }
+                                Ok(Received::Control(control)) => {
+                                    wire::handle_control(addr, control, &mut event_channel);
+                                    continue;
+                                }
+                                Err(e) => {
+                                    event_channel
+                                        .single_write(NetworkSimulationEvent::RecvError(e));
+                                    continue;
+                                }
+                            };
+                        if let Some(ref mut sessions) = resource.sessions {
+                            if let Some(event) = sessions.on_packet(addr) {
+                                event_channel.single_write(event);
+                            }
+                        }
+                        wire::deliver(
+                            addr,
+                            header,
+                            payload,
+                            &mut reassembler,
+                            &mut gate,
+                            &mut event_channel,
+                        );
+                    }
+                    SocketEvent::Connect(addr) => {
+                        if let Some(ref mut sessions) = resource.sessions {
+                            if sessions.on_connect(addr) {
+                                continue;
+                            }
+                        }
+                        event_channel.single_write(NetworkSimulationEvent::Connect(addr));
+                    }
+                    SocketEvent::Timeout(addr) => {
+                        resource.kicked.remove(&addr);
+                        resource.peer_stats.remove(&addr);
+                        if let Some(ref mut sessions) = resource.sessions {
+                            sessions.on_timeout(addr, Instant::now());
+                        }
+                        event_channel.single_write(NetworkSimulationEvent::Disconnect(addr));
+                    }
+                }
+            }
+        }
+
+        if let Some(ref datagram_socket) = resource.datagram_socket {
+            loop {
+                match datagram_socket.recv_from(&mut resource.datagram_buffer) {
+                    Ok((recv_len, addr)) => {
+                        event_channel.single_write(NetworkSimulationEvent::Datagram {
+                            addr,
+                            bytes: Bytes::copy_from_slice(&resource.datagram_buffer[..recv_len]),
+                        });
+                    }
+                    Err(e) => {
+                        if e.kind() != io::ErrorKind::WouldBlock {
+                            event_channel.single_write(NetworkSimulationEvent::RecvError(e));
+                        }
+                        break;
+                    }
+                }
             }
         }
     }
 }
 
+/// Keeps track of the session tokens presented by peers so a peer reconnecting after a timeout can
+/// be told apart from a new one.
+#[derive(Default)]
+struct SessionTracker {
+    /// Tokens of the currently connected peers.
+    active: HashMap<SocketAddr, u64>,
+    /// Tokens of the peers which timed out and may resume their session, along with when they
+    /// timed out.
+    resumable: HashMap<SocketAddr, (u64, Instant)>,
+    /// Peers with a resumable session whose connect event is held back until they present a token.
+    pending_connects: HashSet<SocketAddr>,
+}
+
+impl SessionTracker {
+    /// Returns true if the connect event should be held back.
+    fn on_connect(&mut self, addr: SocketAddr) -> bool {
+        if self.resumable.contains_key(&addr) {
+            self.pending_connects.insert(addr);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn on_token(&mut self, addr: SocketAddr, token: u64) -> Option<NetworkSimulationEvent> {
+        self.active.insert(addr, token);
+        let pending_connect = self.pending_connects.remove(&addr);
+        match self.resumable.remove(&addr) {
+            Some((prior_token, _)) if prior_token == token => {
+                Some(NetworkSimulationEvent::SessionResumed { addr, token })
+            }
+            _ if pending_connect => Some(NetworkSimulationEvent::Connect(addr)),
+            _ => None,
+        }
+    }
+
+    /// Releases the held back connect event of a peer which sent a message without presenting a
+    /// token first.
+    fn on_packet(&mut self, addr: SocketAddr) -> Option<NetworkSimulationEvent> {
+        if self.pending_connects.remove(&addr) {
+            self.resumable.remove(&addr);
+            Some(NetworkSimulationEvent::Connect(addr))
+        } else {
+            None
+        }
+    }
+
+    fn on_timeout(&mut self, addr: SocketAddr, now: Instant) {
+        self.pending_connects.remove(&addr);
+        if let Some(token) = self.active.remove(&addr) {
+            self.resumable.insert(addr, (token, now));
+        }
+    }
+
+    /// Forgets the sessions which weren't resumed within the window, releasing the held back
+    /// connect events of their peers.
+    fn evict(&mut self, now: Instant, window: Duration) -> Vec<NetworkSimulationEvent> {
+        let pending_connects = &mut self.pending_connects;
+        let mut connects = Vec::new();
+        self.resumable.retain(|addr, (_, timed_out_at)| {
+            if now.duration_since(*timed_out_at) < window {
+                return true;
+            }
+            if pending_connects.remove(addr) {
+                connects.push(NetworkSimulationEvent::Connect(*addr));
+            }
+            false
+        });
+        connects
+    }
+}
+
 /// Resource that owns the Laminar socket.
 pub struct LaminarSocketResource {
     socket: Option<LaminarSocket>,
+    config: Option<LaminarConfig>,
+    datagram_socket: Option<UdpSocket>,
+    datagram_buffer: Vec<u8>,
+    sessions: Option<SessionTracker>,
+    session_resume_window: Duration,
+    kicked: HashSet<SocketAddr>,
+    peer_stats: HashMap<SocketAddr, PeerStats>,
+    congestion_thresholds: CongestionThresholds,
+    max_recv_events_per_tick: Option<usize>,
 }
 
 impl Default for LaminarSocketResource {
     fn default() -> Self {
-        Self { socket: None }
+        Self {
+            socket: None,
+            config: None,
+            datagram_socket: None,
+            datagram_buffer: Vec::new(),
+            sessions: None,
+            session_resume_window: DEFAULT_SESSION_RESUME_WINDOW,
+            kicked: HashSet::new(),
+            peer_stats: HashMap::new(),
+            congestion_thresholds: CongestionThresholds::default(),
+            max_recv_events_per_tick: None,
+        }
     }
 }
 
 impl LaminarSocketResource {
     /// Creates a new instance of the `UdpSocketResource`.
     pub fn new(socket: Option<LaminarSocket>) -> Self {
-        Self { socket }
+        Self {
+            socket,
+            config: None,
+            datagram_socket: None,
+            datagram_buffer: Vec::new(),
+            sessions: None,
+            session_resume_window: DEFAULT_SESSION_RESUME_WINDOW,
+            kicked: HashSet::new(),
+            peer_stats: HashMap::new(),
+            congestion_thresholds: CongestionThresholds::default(),
+            max_recv_events_per_tick: None,
+        }
+    }
+
+    /// Creates a new instance of the `LaminarSocketResource` with a socket bound with the given
+    /// config.
+    pub fn with_config(socket: LaminarSocket, config: LaminarConfig) -> Self {
+        Self {
+            config: Some(config),
+            ..Self::new(Some(socket))
+        }
+    }
+
+    /// Returns the config the socket was bound with, if known. It is unknown for sockets bound
+    /// outside of the `LaminarNetworkBundle` and `with_config`.
+    pub fn config(&self) -> Option<&LaminarConfig> {
+        self.config.as_ref()
     }
 
     /// Returns a reference to the socket if there is one configured.
@@ -196,10 +494,725 @@ impl LaminarSocketResource {
     /// Sets the bound socket to the `LaminarSocketResource`.
     pub fn set_socket(&mut self, socket: LaminarSocket) {
         self.socket = Some(socket);
+        self.config = None;
     }
 
     /// Drops the socket from the `LaminarSocketResource`.
     pub fn drop_socket(&mut self) {
         self.socket = None;
+        self.config = None;
+    }
+
+    /// Returns the maximum number of socket events processed per tick, if capped.
+    pub fn max_recv_events_per_tick(&self) -> Option<usize> {
+        self.max_recv_events_per_tick
+    }
+
+    /// Caps the number of socket events the receive system processes per tick, so a large burst
+    /// after the socket wasn't polled for a while is spread over several frames. The remaining
+    /// events stay queued in laminar until the next tick. `None` processes every queued event.
+    pub fn set_max_recv_events_per_tick(&mut self, max_events: Option<usize>) {
+        self.max_recv_events_per_tick = max_events;
+    }
+
+    /// Enables or disables session resumption. When enabled, peers may present a session token
+    /// with `present_session_token` after connecting. If a peer times out and later reconnects
+    /// from the same address presenting the same token, a `NetworkSimulationEvent::SessionResumed`
+    /// is emitted instead of a `NetworkSimulationEvent::Connect`.
+    pub fn set_session_resumption(&mut self, enabled: bool) {
+        if !enabled {
+            self.sessions = None;
+        } else if self.sessions.is_none() {
+            self.sessions = Some(SessionTracker::default());
+        }
+    }
+
+    /// Returns how long a timed out peer may take to reconnect and resume its session.
+    pub fn session_resume_window(&self) -> Duration {
+        self.session_resume_window
+    }
+
+    /// Sets how long a timed out peer may take to reconnect and resume its session, 60 seconds by
+    /// default. Past the window its session is forgotten and a reconnect is a fresh connect.
+    pub fn set_session_resume_window(&mut self, window: Duration) {
+        self.session_resume_window = window;
+    }
+
+    /// Returns the session token presented by the connected peer at the given address.
+    pub fn session_token(&self, addr: SocketAddr) -> Option<u64> {
+        self.sessions
+            .as_ref()
+            .and_then(|sessions| sessions.active.get(&addr).copied())
+    }
+
+    /// Sends the session token to the peer at the given address as a control message. The token
+    /// is sent reliably and ordered on the default stream, so it arrives before any message sent
+    /// afterwards with the `Default` delivery requirement.
+    pub fn present_session_token(&mut self, addr: SocketAddr, token: u64) -> Result<(), ErrorKind> {
+        match self.socket {
+            Some(ref mut socket) => socket.send(Packet::reliable_ordered(
+                addr,
+                wire::encode_control(&Control::SessionToken(token)),
+                None,
+            )),
+            None => Err(ErrorKind::IOError(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "No laminar socket configured",
+            ))),
+        }
+    }
+
+    /// Kicks the peer at the given address. The reason is sent reliably as a control message on
+    /// the `CONTROL_CHANNEL` stream and surfaces on the peer as a `NetworkSimulationEvent::Kicked`,
+    /// then every packet received from the peer is dropped until its connection times out. Messages
+    /// sent to the peer meanwhile are dropped as `DropReason::NotConnected`, so they don't keep its
+    /// end of the connection alive.
+    pub fn kick(&mut self, addr: SocketAddr, reason: &str) -> Result<(), ErrorKind> {
+        match self.socket {
+            Some(ref mut socket) => {
+                let kick = wire::encode_control(&Control::Kick {
+                    reason: reason.to_owned(),
+                });
+                socket.send(Packet::reliable_ordered(addr, kick, Some(CONTROL_CHANNEL)))?;
+                self.kicked.insert(addr);
+                Ok(())
+            }
+            None => Err(ErrorKind::IOError(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "No laminar socket configured",
+            ))),
+        }
+    }
+
+    /// Returns the connection statistics of the peer at the given address, if any.
+    pub fn peer_stats(&self, addr: SocketAddr) -> Option<&PeerStats> {
+        self.peer_stats.get(&addr)
+    }
+
+    /// Reports the round-trip time and packet loss of the peer at the given address, as measured
+    /// by the game, e.g. with ping messages. laminar 0.3 keeps its own estimates private to its
+    /// connections, so the transport doesn't measure them and they stay at zero until reported.
+    /// The queue depth is kept up to date by the send system.
+    pub fn report_peer_stats(&mut self, addr: SocketAddr, rtt: Duration, packet_loss: f32) {
+        let stats = self.peer_stats.entry(addr).or_default();
+        stats.rtt = rtt;
+        stats.packet_loss = packet_loss;
+    }
+
+    /// Returns the thresholds the congestion levels are derived with.
+    pub fn congestion_thresholds(&self) -> &CongestionThresholds {
+        &self.congestion_thresholds
+    }
+
+    /// Sets the thresholds the congestion levels are derived with.
+    pub fn set_congestion_thresholds(&mut self, thresholds: CongestionThresholds) {
+        self.congestion_thresholds = thresholds;
+    }
+
+    /// Returns the congestion level of the peer at the given address, or `None` if there are no
+    /// statistics for it. The statistics of a peer are dropped when its connection times out.
+    ///
+    /// Until stats are reported with `report_peer_stats`, the level only reflects the queue depth,
+    /// which is `Good` unless messages pile up in the `TransportResource`.
+    pub fn congestion_level(&self, addr: SocketAddr) -> Option<CongestionLevel> {
+        self.peer_stats(addr)
+            .map(|stats| CongestionLevel::from_stats(stats, &self.congestion_thresholds))
+    }
+
+    /// Sets the queue depth of every peer to its number of messages waiting to be sent.
+    fn update_queue_depths(&mut self, transport: &TransportResource) {
+        for stats in self.peer_stats.values_mut() {
+            stats.queue_depth = 0;
+        }
+        for message in transport.get_messages() {
+            self.peer_stats
+                .entry(message.destination)
+                .or_default()
+                .queue_depth += 1;
+        }
+    }
+
+    /// Returns a reference to the raw datagram socket if there is one configured.
+    pub fn get_datagram_socket(&self) -> Option<&UdpSocket> {
+        self.datagram_socket.as_ref()
+    }
+
+    /// Sets the auxiliary socket used for raw datagrams. Datagrams received on it are emitted as
+    /// `NetworkSimulationEvent::Datagram` and never go through laminar's virtual connections.
+    pub fn set_datagram_socket(
+        &mut self,
+        socket: UdpSocket,
+        recv_buffer_size_bytes: usize,
+    ) -> io::Result<()> {
+        socket.set_nonblocking(true)?;
+        self.datagram_socket = Some(socket);
+        self.datagram_buffer = vec![0; recv_buffer_size_bytes];
+        Ok(())
+    }
+
+    /// Drops the raw datagram socket from the `LaminarSocketResource`.
+    pub fn drop_datagram_socket(&mut self) {
+        self.datagram_socket = None;
+    }
+
+    /// Sends a raw datagram to the given address, bypassing laminar entirely. Enable broadcast on
+    /// the datagram socket with `UdpSocket::set_broadcast` to send to a broadcast address.
+    pub fn send_datagram(&self, addr: SocketAddr, payload: &[u8]) -> io::Result<usize> {
+        match self.datagram_socket {
+            Some(ref socket) => socket.send_to(payload, addr),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "No datagram socket configured",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amethyst_core::{
+        ecs::{RunNow, WorldExt},
+        shrev::ReaderId,
+    };
+    use std::{net::Ipv4Addr, thread, time::Duration};
+
+    #[test]
+    fn test_bundle_records_network_schedule() {
+        let mut world = World::new();
+        let mut builder = DispatcherBuilder::new();
+        LaminarNetworkBundle::new(None)
+            .build(&mut world, &mut builder)
+            .unwrap();
+        let schedule = world.fetch::<NetworkSchedule>();
+        assert_eq!(
+            schedule.system_names(),
+            vec![
+                NETWORK_SIM_TIME_SYSTEM_NAME,
+                NETWORK_SEND_SYSTEM_NAME,
+                NETWORK_POLL_SYSTEM_NAME,
+                NETWORK_RECV_SYSTEM_NAME,
+            ]
+        );
+        assert_eq!(
+            schedule.systems().last(),
+            Some((NETWORK_RECV_SYSTEM_NAME, &[NETWORK_POLL_SYSTEM_NAME][..]))
+        );
+        assert_eq!(
+            schedule.to_string(),
+            "1. simulation_time\n\
+             2. network_send (after simulation_time)\n\
+             3. network_poll (after network_send)\n\
+             4. network_recv (after network_poll)"
+        );
+    }
+
+    #[test]
+    fn test_bundle_binds_socket_with_config() {
+        let config = LaminarConfig {
+            idle_connection_timeout: Duration::from_millis(1234),
+            ..Default::default()
+        };
+        let mut world = World::new();
+        let mut builder = DispatcherBuilder::new();
+        LaminarNetworkBundle::bind_with_config("127.0.0.1:0", config)
+            .build(&mut world, &mut builder)
+            .unwrap();
+        let resource = world.fetch::<LaminarSocketResource>();
+        assert!(resource.get().unwrap().local_addr().is_ok());
+        assert_eq!(
+            resource.config().unwrap().idle_connection_timeout,
+            Duration::from_millis(1234)
+        );
+
+        // The bind error surfaces from the build
+        let taken_addr = resource.get().unwrap().local_addr().unwrap();
+        let mut builder = DispatcherBuilder::new();
+        assert!(LaminarNetworkBundle::bind(taken_addr)
+            .build(&mut World::new(), &mut builder)
+            .is_err());
+    }
+
+    #[test]
+    fn test_recv_events_per_tick_are_capped() {
+        let server = LaminarSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let mut server_resource = LaminarSocketResource::new(Some(server));
+        server_resource.set_max_recv_events_per_tick(Some(5));
+
+        let mut world = World::new();
+        world.insert(EventChannel::<NetworkSimulationEvent>::new());
+        world.insert(ReceiveGate::new());
+        world.insert(FragmentReassembler::default());
+        world.insert(server_resource);
+        let mut reader = world
+            .fetch_mut::<EventChannel<NetworkSimulationEvent>>()
+            .register_reader();
+
+        let mut client = LaminarSocket::bind("127.0.0.1:0").unwrap();
+        for i in 0..20u8 {
+            client
+                .send(Packet::unreliable(server_addr, wire::encode_message(&[i])))
+                .unwrap();
+        }
+        client.manual_poll(Instant::now());
+
+        let mut per_tick = Vec::new();
+        let mut messages = 0;
+        for _ in 0..100 {
+            world
+                .fetch_mut::<LaminarSocketResource>()
+                .get_mut()
+                .unwrap()
+                .manual_poll(Instant::now());
+            LaminarNetworkRecvSystem.run_now(&world);
+            let events = world
+                .fetch::<EventChannel<NetworkSimulationEvent>>()
+                .read(&mut reader)
+                .map(|event| matches!(event, NetworkSimulationEvent::Message(..)))
+                .collect::<Vec<_>>();
+            per_tick.push(events.len());
+            messages += events.into_iter().filter(|&message| message).count();
+            if messages == 20 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(messages, 20);
+        assert!(per_tick.iter().all(|&events| events <= 5));
+        assert!(per_tick.len() >= 5);
+    }
+
+    #[test]
+    fn test_sessions_not_resumed_within_window_are_evicted() {
+        let window = Duration::from_secs(10);
+        let addr = "127.0.0.1:3000".parse().unwrap();
+        let now = Instant::now();
+        let mut sessions = SessionTracker::default();
+        sessions.on_token(addr, 42);
+        sessions.on_timeout(addr, now);
+
+        // The reconnect is held back until the peer presents its token or the window elapses
+        assert!(sessions.on_connect(addr));
+        assert!(sessions.evict(now + window / 2, window).is_empty());
+        assert_eq!(
+            format!("{:?}", sessions.evict(now + window, window)),
+            format!("{:?}", vec![NetworkSimulationEvent::Connect(addr)])
+        );
+        assert!(!sessions.on_connect(addr));
+        assert_eq!(
+            format!("{:?}", sessions.on_token(addr, 42)),
+            format!("{:?}", None::<NetworkSimulationEvent>)
+        );
+    }
+
+    #[test]
+    fn test_reconnect_with_matching_token_resumes_session() {
+        let config = LaminarConfig {
+            idle_connection_timeout: Duration::from_millis(50),
+            ..Default::default()
+        };
+
+        let server = LaminarSocket::bind_with_config("127.0.0.1:0", config.clone()).unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let mut server_resource = LaminarSocketResource::new(Some(server));
+        server_resource.set_session_resumption(true);
+
+        let mut world = World::new();
+        world.insert(EventChannel::<NetworkSimulationEvent>::new());
+        world.insert(ReceiveGate::new());
+        world.insert(FragmentReassembler::default());
+        world.insert(server_resource);
+        let mut reader = world
+            .fetch_mut::<EventChannel<NetworkSimulationEvent>>()
+            .register_reader();
+
+        let client = LaminarSocket::bind_with_config("127.0.0.1:0", config.clone()).unwrap();
+        let client_addr = client.local_addr().unwrap();
+        let mut client = LaminarSocketResource::new(Some(client));
+
+        let mut poll = |time: Instant, client: &mut LaminarSocketResource| {
+            client.get_mut().unwrap().manual_poll(time);
+            world
+                .fetch_mut::<LaminarSocketResource>()
+                .get_mut()
+                .unwrap()
+                .manual_poll(time);
+            LaminarNetworkRecvSystem.run_now(&world);
+            world
+                .fetch::<EventChannel<NetworkSimulationEvent>>()
+                .read(&mut reader)
+                .map(|event| format!("{:?}", event))
+                .collect::<Vec<_>>()
+        };
+
+        let now = Instant::now();
+        client.present_session_token(server_addr, 42).unwrap();
+        assert_eq!(
+            poll(now, &mut client),
+            vec![format!(
+                "{:?}",
+                NetworkSimulationEvent::Connect(client_addr)
+            )]
+        );
+        // Acknowledge the client so laminar keeps track of the connection, the second poll
+        // delivers the acknowledgement to the client
+        world
+            .fetch_mut::<LaminarSocketResource>()
+            .get_mut()
+            .unwrap()
+            .send(Packet::unreliable(client_addr, vec![]))
+            .unwrap();
+        assert!(poll(now, &mut client).is_empty());
+        assert!(poll(now, &mut client).is_empty());
+
+        let later = now + config.idle_connection_timeout;
+        assert_eq!(
+            poll(later, &mut client),
+            vec![format!(
+                "{:?}",
+                NetworkSimulationEvent::Disconnect(client_addr)
+            )]
+        );
+
+        client.present_session_token(server_addr, 42).unwrap();
+        assert_eq!(
+            poll(later + Duration::from_millis(1), &mut client),
+            vec![format!(
+                "{:?}",
+                NetworkSimulationEvent::SessionResumed {
+                    addr: client_addr,
+                    token: 42
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_request_carries_correlation_id() {
+        let server = LaminarSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let mut server_world = World::new();
+        server_world.insert(EventChannel::<NetworkSimulationEvent>::new());
+        server_world.insert(ReceiveGate::new());
+        server_world.insert(FragmentReassembler::default());
+        server_world.insert(LaminarSocketResource::new(Some(server)));
+        let mut reader = server_world
+            .fetch_mut::<EventChannel<NetworkSimulationEvent>>()
+            .register_reader();
+
+        let client = LaminarSocket::bind("127.0.0.1:0").unwrap();
+        let client_addr = client.local_addr().unwrap();
+        let mut client_world = World::new();
+        client_world.insert(EventChannel::<NetworkSimulationEvent>::new());
+        client_world.insert(TransportResource::new());
+        client_world.insert(NetworkSimulationTime::default());
+        client_world.insert(DropStats::default());
+        client_world.insert(LaminarSocketResource::new(Some(client)));
+
+        let id = client_world
+            .fetch_mut::<TransportResource>()
+            .request(
+                server_addr,
+                b"ping",
+                DeliveryRequirement::ReliableOrdered(None),
+            )
+            .unwrap();
+        LaminarNetworkSendSystem.run_now(&client_world);
+        client_world
+            .fetch_mut::<LaminarSocketResource>()
+            .get_mut()
+            .unwrap()
+            .manual_poll(Instant::now());
+
+        let mut received = None;
+        for _ in 0..100 {
+            server_world
+                .fetch_mut::<LaminarSocketResource>()
+                .get_mut()
+                .unwrap()
+                .manual_poll(Instant::now());
+            LaminarNetworkRecvSystem.run_now(&server_world);
+            received = server_world
+                .fetch::<EventChannel<NetworkSimulationEvent>>()
+                .read(&mut reader)
+                .find_map(|event| match event {
+                    NetworkSimulationEvent::Message(addr, payload, correlation_id) => {
+                        Some((*addr, payload.clone(), *correlation_id))
+                    }
+                    _ => None,
+                });
+            if received.is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(
+            received,
+            Some((client_addr, Bytes::from_static(b"ping"), Some(id)))
+        );
+    }
+
+    #[test]
+    fn test_kicked_peer_receives_reason_and_is_dropped() {
+        let new_world = |socket: LaminarSocket| {
+            let mut world = World::new();
+            world.insert(EventChannel::<NetworkSimulationEvent>::new());
+            world.insert(ReceiveGate::new());
+            world.insert(FragmentReassembler::default());
+            world.insert(LaminarSocketResource::new(Some(socket)));
+            let reader = world
+                .fetch_mut::<EventChannel<NetworkSimulationEvent>>()
+                .register_reader();
+            (world, reader)
+        };
+        let poll = |world: &World, reader: &mut ReaderId<NetworkSimulationEvent>| {
+            world
+                .fetch_mut::<LaminarSocketResource>()
+                .get_mut()
+                .unwrap()
+                .manual_poll(Instant::now());
+            LaminarNetworkRecvSystem.run_now(world);
+            world
+                .fetch::<EventChannel<NetworkSimulationEvent>>()
+                .read(reader)
+                .map(|event| format!("{:?}", event))
+                .collect::<Vec<_>>()
+        };
+
+        let server = LaminarSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let (server, mut server_reader) = new_world(server);
+        let client = LaminarSocket::bind("127.0.0.1:0").unwrap();
+        let client_addr = client.local_addr().unwrap();
+        let (client, mut client_reader) = new_world(client);
+
+        server
+            .fetch_mut::<LaminarSocketResource>()
+            .kick(client_addr, "cheating")
+            .unwrap();
+        poll(&server, &mut server_reader);
+        let kicked = format!(
+            "{:?}",
+            NetworkSimulationEvent::Kicked {
+                addr: server_addr,
+                reason: "cheating".to_owned()
+            }
+        );
+        assert!(poll(&client, &mut client_reader).contains(&kicked));
+
+        client
+            .fetch_mut::<LaminarSocketResource>()
+            .get_mut()
+            .unwrap()
+            .send(Packet::reliable_ordered(
+                server_addr,
+                wire::encode_message(b"hello"),
+                None,
+            ))
+            .unwrap();
+        poll(&client, &mut client_reader);
+        // Another peer sending afterwards shows the server received the kicked peer's packet. Its
+        // payload looks like a kick but is delivered as a message, since it isn't flagged as one.
+        let lookalike = wire::encode_control(&Control::Kick {
+            reason: "cheating".to_owned(),
+        });
+        let mut other = LaminarSocket::bind("127.0.0.1:0").unwrap();
+        let other_addr = other.local_addr().unwrap();
+        other
+            .send(Packet::reliable_ordered(
+                server_addr,
+                wire::encode_message(&lookalike),
+                None,
+            ))
+            .unwrap();
+        other.manual_poll(Instant::now());
+        let mut server_events = Vec::new();
+        for _ in 0..100 {
+            server_events.extend(poll(&server, &mut server_reader));
+            if server_events
+                .iter()
+                .any(|event| event.starts_with("Message"))
+            {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        let messages = server_events
+            .iter()
+            .filter(|event| event.starts_with("Message"))
+            .collect::<Vec<_>>();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            messages[0],
+            &format!(
+                "{:?}",
+                NetworkSimulationEvent::Message(other_addr, Bytes::from(lookalike), None)
+            )
+        );
+        assert!(!server_events
+            .iter()
+            .any(|event| event.starts_with("Kicked")));
+    }
+
+    #[test]
+    fn test_nothing_is_sent_to_kicked_peer() {
+        let server = LaminarSocket::bind("127.0.0.1:0").unwrap();
+        let mut server_world = World::new();
+        server_world.insert(EventChannel::<NetworkSimulationEvent>::new());
+        server_world.insert(TransportResource::new());
+        server_world.insert(NetworkSimulationTime::default());
+        server_world.insert(DropStats::default());
+        server_world.insert(LaminarSocketResource::new(Some(server)));
+
+        let mut client = LaminarSocket::bind("127.0.0.1:0").unwrap();
+        let client_addr = client.local_addr().unwrap();
+
+        server_world
+            .fetch_mut::<LaminarSocketResource>()
+            .kick(client_addr, "cheating")
+            .unwrap();
+        server_world
+            .fetch_mut::<TransportResource>()
+            .send(client_addr, b"hello");
+        LaminarNetworkSendSystem.run_now(&server_world);
+        server_world
+            .fetch_mut::<LaminarSocketResource>()
+            .get_mut()
+            .unwrap()
+            .manual_poll(Instant::now());
+        assert_eq!(
+            server_world
+                .fetch::<DropStats>()
+                .count(DropReason::NotConnected),
+            1
+        );
+        assert!(server_world
+            .fetch::<TransportResource>()
+            .get_messages()
+            .is_empty());
+
+        // Only the kick reaches the peer
+        let mut received = Vec::new();
+        for _ in 0..50 {
+            client.manual_poll(Instant::now());
+            while let Some(event) = client.recv() {
+                if let SocketEvent::Packet(packet) = event {
+                    received.push(wire::decode(Bytes::copy_from_slice(packet.payload())).unwrap());
+                }
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(
+            received,
+            vec![Received::Control(Control::Kick {
+                reason: "cheating".to_owned()
+            })]
+        );
+    }
+
+    #[test]
+    fn test_congestion_level_of_peer_with_high_rtt_and_loss_is_poor() {
+        let mut resource = LaminarSocketResource::new(None);
+        let addr = "127.0.0.1:3000".parse().unwrap();
+        assert_eq!(resource.congestion_level(addr), None);
+
+        resource.report_peer_stats(addr, Duration::from_millis(400), 0.25);
+        assert_eq!(resource.congestion_level(addr), Some(CongestionLevel::Poor));
+    }
+
+    #[test]
+    fn test_queue_depth_is_updated_from_queued_messages() {
+        let mut resource = LaminarSocketResource::new(None);
+        let addr = "127.0.0.1:3000".parse().unwrap();
+        let mut transport = TransportResource::new();
+        for _ in 0..3 {
+            transport.send(addr, b"test");
+        }
+
+        resource.update_queue_depths(&transport);
+        assert_eq!(resource.peer_stats(addr).unwrap().queue_depth, 3);
+
+        transport.drain_messages(|_| true);
+        resource.update_queue_depths(&transport);
+        assert_eq!(resource.peer_stats(addr).unwrap().queue_depth, 0);
+    }
+
+    /// Sends a discovery datagram from a fresh socket to the given address and returns the
+    /// datagrams received on the receiving socket.
+    fn exchange_discovery_datagram(
+        receiver: UdpSocket,
+        sender: UdpSocket,
+        destination: impl Fn(SocketAddr) -> SocketAddr,
+    ) -> Vec<(SocketAddr, Bytes)> {
+        let mut world = World::new();
+        world.insert(EventChannel::<NetworkSimulationEvent>::new());
+        world.insert(ReceiveGate::new());
+        world.insert(FragmentReassembler::default());
+        let mut reader = world
+            .fetch_mut::<EventChannel<NetworkSimulationEvent>>()
+            .register_reader();
+
+        let receiver_addr = receiver.local_addr().unwrap();
+        let mut receiver_resource = LaminarSocketResource::new(None);
+        receiver_resource
+            .set_datagram_socket(receiver, 1500)
+            .unwrap();
+        world.insert(receiver_resource);
+
+        let mut sender_resource = LaminarSocketResource::new(None);
+        sender_resource.set_datagram_socket(sender, 1500).unwrap();
+        sender_resource
+            .send_datagram(destination(receiver_addr), b"discover")
+            .unwrap();
+
+        let mut received = Vec::new();
+        for _ in 0..100 {
+            LaminarNetworkRecvSystem.run_now(&world);
+            received.extend(
+                world
+                    .fetch::<EventChannel<NetworkSimulationEvent>>()
+                    .read(&mut reader)
+                    .filter_map(|event| match event {
+                        NetworkSimulationEvent::Datagram { addr, bytes } => {
+                            Some((*addr, bytes.clone()))
+                        }
+                        _ => None,
+                    }),
+            );
+            if !received.is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        received
+    }
+
+    #[test]
+    fn test_unicast_datagram_is_received_on_another_socket() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sender_addr = sender.local_addr().unwrap();
+
+        assert_eq!(
+            exchange_discovery_datagram(receiver, sender, |addr| addr),
+            vec![(sender_addr, Bytes::from_static(b"discover"))]
+        );
+    }
+
+    #[test]
+    #[ignore] // Sandboxed CI environments may block broadcast
+    fn test_broadcast_datagram_is_received_on_another_socket() {
+        let receiver = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let sender = UdpSocket::bind("0.0.0.0:0").unwrap();
+        sender.set_broadcast(true).unwrap();
+        let sender_port = sender.local_addr().unwrap().port();
+
+        let received = exchange_discovery_datagram(receiver, sender, |addr| {
+            SocketAddr::new(Ipv4Addr::BROADCAST.into(), addr.port())
+        });
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].0.port(), sender_port);
+        assert_eq!(received[0].1, Bytes::from_static(b"discover"));
     }
 }
@@ -1,14 +1,19 @@
 //! Network systems implementation backed by the TCP network protocol.
 
 use crate::simulation::{
+    drop_stats::{DropReason, DropStats},
     events::NetworkSimulationEvent,
+    fragmentation::FragmentReassembler,
+    gate::ReceiveGate,
     message::Message,
     requirements::DeliveryRequirement,
+    schedule::NetworkSchedule,
     timing::{NetworkSimulationTime, NetworkSimulationTimeSystem},
     transport::{
         TransportResource, NETWORK_RECV_SYSTEM_NAME, NETWORK_SEND_SYSTEM_NAME,
         NETWORK_SIM_TIME_SYSTEM_NAME,
     },
+    wire::{self, Control, Header},
 };
 use amethyst_core::{
     bundle::SystemBundle,
@@ -16,22 +21,44 @@ use amethyst_core::{
     shrev::EventChannel,
 };
 use amethyst_error::Error;
-use bytes::Bytes;
+use bytes::{Buf, Bytes, BytesMut};
 use log::warn;
+use socket2::Socket;
+#[cfg(unix)]
+use std::os::unix::io::{FromRawFd, RawFd};
+#[cfg(windows)]
+use std::os::windows::io::{FromRawSocket, RawSocket};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet, VecDeque},
+    convert::TryFrom,
     io::{self, Read as IORead, Write as IOWrite},
-    net::{SocketAddr, TcpListener, TcpStream},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
     ops::DerefMut,
+    time::{Duration, Instant},
 };
 
 const CONNECTION_LISTENER_SYSTEM_NAME: &str = "connection_listener";
 const STREAM_MANAGEMENT_SYSTEM_NAME: &str = "stream_management";
 
+/// Size of the length header prefixed to every message when framing is enabled.
+const FRAME_HEADER_SIZE: usize = 4;
+/// Default upper bound of the size of a frame, header of the transports included.
+const DEFAULT_MAX_FRAME_BYTES: usize = 1024 * 1024;
+/// Delay before a reconnection attempt scheduled while automatic reconnection is disabled.
+const DEFAULT_RECONNECT_BACKOFF: Duration = Duration::from_secs(1);
+/// Upper bound of the delay between two reconnection attempts.
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(30);
+
 /// Use this network bundle to add the TCP transport layer to your game.
 pub struct TcpNetworkBundle {
     listener: Option<TcpListener>,
     recv_buffer_size_bytes: usize,
+    linger: Option<Duration>,
+    framing: bool,
+    reconnect_backoff: Option<Duration>,
+    max_send_queue_bytes: Option<usize>,
+    max_frame_bytes: usize,
+    read_timeout: Option<Duration>,
 }
 
 impl TcpNetworkBundle {
@@ -39,8 +66,53 @@ impl TcpNetworkBundle {
         Self {
             listener,
             recv_buffer_size_bytes,
+            linger: None,
+            framing: false,
+            reconnect_backoff: None,
+            max_send_queue_bytes: None,
+            max_frame_bytes: DEFAULT_MAX_FRAME_BYTES,
+            read_timeout: None,
         }
     }
+
+    /// Sets the `SO_LINGER` duration applied to every stream, see
+    /// `TcpNetworkResource::set_linger`.
+    pub fn with_linger(mut self, linger: Duration) -> Self {
+        self.linger = Some(linger);
+        self
+    }
+
+    /// Enables length-prefix framing of messages, see `TcpNetworkResource::set_framing`.
+    pub fn with_framing(mut self) -> Self {
+        self.framing = true;
+        self
+    }
+
+    /// Enables automatic reconnection, see `TcpNetworkResource::set_reconnect_backoff`.
+    pub fn with_reconnect_backoff(mut self, backoff: Duration) -> Self {
+        self.reconnect_backoff = Some(backoff);
+        self
+    }
+
+    /// Bounds the send queue of every stream, see `TcpNetworkResource::set_max_send_queue_bytes`.
+    pub fn with_max_send_queue_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_send_queue_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Bounds the size of the frames sent and received, see
+    /// `TcpNetworkResource::set_max_frame_bytes`.
+    pub fn with_max_frame_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_frame_bytes = max_bytes;
+        self
+    }
+
+    /// Puts the streams in blocking mode with the given read timeout, see
+    /// `TcpNetworkResource::set_read_timeout`.
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
 }
 
 impl<'a, 'b> SystemBundle<'a, 'b> for TcpNetworkBundle {
@@ -49,29 +121,34 @@ impl<'a, 'b> SystemBundle<'a, 'b> for TcpNetworkBundle {
         world: &mut World,
         builder: &mut DispatcherBuilder<'_, '_>,
     ) -> Result<(), Error> {
+        let mut schedule = world.remove::<NetworkSchedule>().unwrap_or_default();
         // NetworkSimulationTime should run first
         // followed by TcpConnectionListenerSystem and TcpStreamManagementSystem
         // then TcpNetworkSendSystem and TcpNetworkRecvSystem
 
-        builder.add(
+        schedule.add(
+            builder,
             NetworkSimulationTimeSystem,
             NETWORK_SIM_TIME_SYSTEM_NAME,
             &[],
         );
 
-        builder.add(
+        schedule.add(
+            builder,
             TcpConnectionListenerSystem,
             CONNECTION_LISTENER_SYSTEM_NAME,
             &[NETWORK_SIM_TIME_SYSTEM_NAME],
         );
 
-        builder.add(
+        schedule.add(
+            builder,
             TcpStreamManagementSystem,
             STREAM_MANAGEMENT_SYSTEM_NAME,
             &[NETWORK_SIM_TIME_SYSTEM_NAME],
         );
 
-        builder.add(
+        schedule.add(
+            builder,
             TcpNetworkSendSystem,
             NETWORK_SEND_SYSTEM_NAME,
             &[
@@ -80,7 +157,8 @@ impl<'a, 'b> SystemBundle<'a, 'b> for TcpNetworkBundle {
             ],
         );
 
-        builder.add(
+        schedule.add(
+            builder,
             TcpNetworkRecvSystem,
             NETWORK_RECV_SYSTEM_NAME,
             &[
@@ -89,10 +167,15 @@ impl<'a, 'b> SystemBundle<'a, 'b> for TcpNetworkBundle {
             ],
         );
 
-        world.insert(TcpNetworkResource::new(
-            self.listener,
-            self.recv_buffer_size_bytes,
-        ));
+        let mut resource = TcpNetworkResource::new(self.listener, self.recv_buffer_size_bytes);
+        resource.set_linger(self.linger);
+        resource.set_framing(self.framing);
+        resource.set_reconnect_backoff(self.reconnect_backoff);
+        resource.set_max_send_queue_bytes(self.max_send_queue_bytes);
+        resource.set_max_frame_bytes(self.max_frame_bytes);
+        resource.set_read_timeout(self.read_timeout);
+        world.insert(resource);
+        world.insert(schedule);
         Ok(())
     }
 }
@@ -107,39 +190,103 @@ impl<'s> System<'s> for TcpStreamManagementSystem {
         Write<'s, EventChannel<NetworkSimulationEvent>>,
     );
 
-    // We cannot use `net.streams.entry(message.destination).or_insert_with(|| { .. })` because
-    // there is a `return;` statement for early exit, which is not allowed within the closure.
-    #[allow(clippy::map_entry)]
     fn run(&mut self, (mut net, transport, mut event_channel): Self::SystemData) {
-        // Make connections for each message in the channel if one hasn't yet been established
-        transport.get_messages().iter().for_each(|message| {
-            if !net.streams.contains_key(&message.destination) {
-                let s = match TcpStream::connect(message.destination) {
-                    Ok(s) => s,
+        let resource = net.deref_mut();
+
+        // Make connections for each message in the channel if one hasn't yet been established.
+        // Addresses waiting for a reconnection or whose reconnection was cancelled are left to
+        // the backoff schedule.
+        for message in transport.get_messages() {
+            if !resource.streams.contains_key(&message.destination)
+                && !resource.reconnects.contains_key(&message.destination)
+                && !resource.cancelled.contains(&message.destination)
+            {
+                match connect(message.destination, resource.linger, resource.read_timeout) {
+                    Ok(s) => {
+                        resource.outbound.insert(message.destination);
+                        resource.streams.insert(message.destination, (true, s));
+                    }
                     Err(e) => {
                         event_channel.single_write(NetworkSimulationEvent::ConnectionError(
                             e,
                             Some(message.destination),
                         ));
-                        return;
+                        if resource.reconnect_backoff.is_some() {
+                            resource.schedule_reconnect(message.destination);
+                        }
                     }
-                };
-                s.set_nonblocking(true).expect("Setting non-blocking mode");
-                s.set_nodelay(true).expect("Setting nodelay");
-                net.streams.insert(message.destination, (true, s));
+                }
             }
-        });
+        }
+
+        // Attempt the scheduled reconnections which are due
+        let now = Instant::now();
+        let due = resource
+            .reconnects
+            .iter()
+            .filter(|(_, (attempt_at, _))| *attempt_at <= now)
+            .map(|(addr, _)| *addr)
+            .collect::<Vec<_>>();
+        for addr in due {
+            if resource.streams.contains_key(&addr) {
+                resource.reconnects.remove(&addr);
+                continue;
+            }
+            match connect(addr, resource.linger, resource.read_timeout) {
+                Ok(s) => {
+                    resource.reconnects.remove(&addr);
+                    resource.outbound.insert(addr);
+                    resource.streams.insert(addr, (true, s));
+                    event_channel.single_write(NetworkSimulationEvent::Connect(addr));
+                }
+                Err(e) => {
+                    event_channel
+                        .single_write(NetworkSimulationEvent::ConnectionError(e, Some(addr)));
+                    if let Some((attempt_at, backoff)) = resource.reconnects.get_mut(&addr) {
+                        *backoff = (*backoff * 2).min(MAX_RECONNECT_BACKOFF);
+                        *attempt_at = now + *backoff;
+                    }
+                }
+            }
+        }
 
         // Remove inactive connections
-        net.streams.retain(|addr, (active, _)| {
+        let frame_buffers = &mut resource.frame_buffers;
+        let send_queues = &mut resource.send_queues;
+        let kicked = &mut resource.kicked;
+        let mut disconnected = Vec::new();
+        resource.streams.retain(|addr, (active, _)| {
             if !*active {
+                frame_buffers.remove(addr);
+                send_queues.remove(addr);
+                kicked.remove(addr);
+                disconnected.push(*addr);
                 event_channel.single_write(NetworkSimulationEvent::Disconnect(*addr));
             }
             *active
         });
+        if resource.reconnect_backoff.is_some() {
+            for addr in disconnected {
+                if resource.outbound.contains(&addr) {
+                    resource.schedule_reconnect(addr);
+                }
+            }
+        }
     }
 }
 
+/// Opens a stream to the given address, non-blocking unless a read timeout is given.
+fn connect(
+    addr: SocketAddr,
+    linger: Option<Duration>,
+    read_timeout: Option<Duration>,
+) -> io::Result<TcpStream> {
+    let s = TcpStream::connect(addr)?;
+    set_blocking_mode(&s, read_timeout).expect("Setting blocking mode");
+    s.set_nodelay(true).expect("Setting nodelay");
+    Ok(apply_linger(s, linger))
+}
+
 /// System to listen for incoming connections and cache them to the resource.
 pub struct TcpConnectionListenerSystem;
 
@@ -151,14 +298,15 @@ impl<'s> System<'s> for TcpConnectionListenerSystem {
 
     fn run(&mut self, (mut net, mut event_channel): Self::SystemData) {
         let resource = net.deref_mut();
+        event_channel.drain_vec_write(&mut resource.pending_events);
         if let Some(ref listener) = resource.listener {
             loop {
                 match listener.accept() {
                     Ok((stream, addr)) => {
-                        stream
-                            .set_nonblocking(true)
-                            .expect("Setting nonblocking mode");
+                        set_blocking_mode(&stream, resource.read_timeout)
+                            .expect("Setting blocking mode");
                         stream.set_nodelay(true).expect("Setting nodelay");
+                        let stream = apply_linger(stream, resource.linger);
                         resource.streams.insert(addr, (true, stream));
                         event_channel.single_write(NetworkSimulationEvent::Connect(addr));
                     }
@@ -176,6 +324,31 @@ impl<'s> System<'s> for TcpConnectionListenerSystem {
     }
 }
 
+/// Puts the stream in blocking mode with the given read timeout, or in non-blocking mode without
+/// one.
+fn set_blocking_mode(stream: &TcpStream, read_timeout: Option<Duration>) -> io::Result<()> {
+    match read_timeout {
+        Some(timeout) => {
+            stream.set_nonblocking(false)?;
+            stream.set_read_timeout(Some(timeout))
+        }
+        None => stream.set_nonblocking(true),
+    }
+}
+
+fn apply_linger(stream: TcpStream, linger: Option<Duration>) -> TcpStream {
+    match linger {
+        Some(linger) => {
+            let socket = Socket::from(stream);
+            if let Err(e) = socket.set_linger(Some(linger)) {
+                warn!("Encountered an error setting linger: {:?}", e);
+            }
+            socket.into_tcp_stream()
+        }
+        None => stream,
+    }
+}
+
 /// System to send messages to a particular open `TcpStream`.
 pub struct TcpNetworkSendSystem;
 
@@ -184,39 +357,218 @@ impl<'s> System<'s> for TcpNetworkSendSystem {
         Write<'s, TransportResource>,
         Write<'s, TcpNetworkResource>,
         Read<'s, NetworkSimulationTime>,
+        Write<'s, DropStats>,
         Write<'s, EventChannel<NetworkSimulationEvent>>,
     );
 
-    fn run(&mut self, (mut transport, mut net, sim_time, mut channel): Self::SystemData) {
+    fn run(
+        &mut self,
+        (mut transport, mut net, sim_time, mut drops, mut channel): Self::SystemData,
+    ) {
         let messages = transport.drain_messages_to_send(|_| sim_time.should_send_message_now());
         for message in messages {
             match message.delivery {
-                DeliveryRequirement::ReliableOrdered(Some(_)) => {
-                    warn!("Streams are not supported by TCP and will be ignored.");
-                    write_message(message, &mut net, &mut channel);
-                }
-                DeliveryRequirement::ReliableOrdered(_) | DeliveryRequirement::Default => {
-                    write_message(message, &mut net, &mut channel);
+                DeliveryRequirement::ReliableOrdered(_)
+                | DeliveryRequirement::ReliableSequenced(_)
+                | DeliveryRequirement::Default => {
+                    write_message(message, &mut net, &mut drops, &mut channel);
                 }
                 delivery => panic!(
-                    "{:?} is unsupported. TCP only supports ReliableOrdered by design.",
+                    "{:?} is unsupported. TCP only supports ReliableOrdered and ReliableSequenced by design.",
                     delivery
                 ),
             }
         }
+        flush_send_queues(&mut net, &mut drops, &mut channel);
+    }
+}
+
+/// A message queued on a stream, possibly partially written already.
+struct PendingWrite {
+    /// The queued message, or `None` for a control message of the transport.
+    message: Option<Message>,
+    bytes: Vec<u8>,
+    written: usize,
+}
+
+impl PendingWrite {
+    fn remaining(&self) -> &[u8] {
+        &self.bytes[self.written..]
     }
 }
 
+/// Queues the message on the send queue of its stream. A `ReliableSequenced` message supersedes
+/// the messages of its stream which aren't written yet, since the peer would only use the newest.
+/// If the queue would exceed its bound, the queue is dropped and the stream marked inactive.
+/// Without framing there is no header, so messages needing one, e.g. requests, are dropped.
 fn write_message(
     message: Message,
     net: &mut TcpNetworkResource,
+    drops: &mut DropStats,
     channel: &mut EventChannel<NetworkSimulationEvent>,
 ) {
-    if let Some((_, stream)) = net.get_stream(message.destination) {
-        if let Err(e) = stream.write(&message.payload) {
+    let bytes = if net.framing {
+        match frame(&message.wire_payload(), net.max_frame_bytes) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                drops.record(DropReason::SendFailed);
+                channel.single_write(NetworkSimulationEvent::SendError(e, message));
+                return;
+            }
+        }
+    } else if message.header().is_empty() {
+        message.payload.to_vec()
+    } else {
+        drops.record(DropReason::SendFailed);
+        let e = io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Message needs a header, which requires framing",
+        );
+        channel.single_write(NetworkSimulationEvent::SendError(e, message));
+        return;
+    };
+    let active = match net.streams.get_mut(&message.destination) {
+        Some((active, _)) if !net.kicked.contains(&message.destination) => active,
+        _ => {
+            drops.record(DropReason::NotConnected);
+            return;
+        }
+    };
+    let queue = net.send_queues.entry(message.destination).or_default();
+    if let DeliveryRequirement::ReliableSequenced(_) = message.delivery {
+        let queued = queue.len();
+        queue.retain(|pending| {
+            pending.written > 0
+                || !matches!(&pending.message, Some(queued) if queued.delivery == message.delivery)
+        });
+        drops.record_many(DropReason::Superseded, (queued - queue.len()) as u64);
+    }
+    if let Some(max_bytes) = net.max_send_queue_bytes {
+        let queued_bytes = queue
+            .iter()
+            .map(|pending| pending.remaining().len())
+            .sum::<usize>();
+        if queued_bytes + bytes.len() > max_bytes {
+            drops.record_many(DropReason::QueueFull, queue.len() as u64 + 1);
+            queue.clear();
+            *active = false;
+            let e = io::Error::other("Send queue of the stream is full");
             channel.single_write(NetworkSimulationEvent::SendError(e, message));
+            return;
+        }
+    }
+    queue.push_back(PendingWrite {
+        message: Some(message),
+        bytes,
+        written: 0,
+    });
+}
+
+/// Writes as much of the send queues as the streams accept, keeping the rest for the next tick.
+/// On an error other than `WouldBlock`, the message being written is dropped with a
+/// `NetworkSimulationEvent::SendError` and the stream marked inactive.
+fn flush_send_queues(
+    net: &mut TcpNetworkResource,
+    drops: &mut DropStats,
+    channel: &mut EventChannel<NetworkSimulationEvent>,
+) {
+    let streams = &mut net.streams;
+    for (addr, queue) in net.send_queues.iter_mut() {
+        let (active, stream) = match streams.get_mut(addr) {
+            Some((active, stream)) if *active => (active, stream),
+            _ => continue,
+        };
+        while let Some(pending) = queue.front_mut() {
+            let result = match stream.write(pending.remaining()) {
+                Ok(0) => Err(io::Error::from(io::ErrorKind::WriteZero)),
+                result => result,
+            };
+            match result {
+                Ok(written) => {
+                    pending.written += written;
+                    if pending.remaining().is_empty() {
+                        queue.pop_front();
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    *active = false;
+                    let dropped = queue.iter().filter(|pending| pending.message.is_some());
+                    drops.record_many(DropReason::SendFailed, dropped.count() as u64);
+                    if let Some(message) = queue.pop_front().and_then(|pending| pending.message) {
+                        channel.single_write(NetworkSimulationEvent::SendError(e, message));
+                    }
+                    queue.clear();
+                    break;
+                }
+            }
         }
     }
+
+    // Close the streams of the kicked peers once everything queued before the kick was written
+    let send_queues = &net.send_queues;
+    net.kicked.retain(|addr| {
+        let (active, stream) = match streams.get_mut(addr) {
+            Some((active, stream)) if *active => (active, stream),
+            _ => return false,
+        };
+        if send_queues.get(addr).map_or(0, VecDeque::len) > 0 {
+            return true;
+        }
+        if let Err(e) = stream.shutdown(Shutdown::Write) {
+            warn!(
+                "Failed to shut down the stream of kicked peer {}: {:?}",
+                addr, e
+            );
+        }
+        *active = false;
+        false
+    });
+}
+
+/// Returns the payload prefixed with its length as a big-endian u32. Fails if the payload is larger
+/// than `max_bytes` or than a u32 can tell.
+fn frame(payload: &[u8], max_bytes: usize) -> io::Result<Vec<u8>> {
+    let len = u32::try_from(payload.len())
+        .ok()
+        .filter(|_| payload.len() <= max_bytes)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Message of {} bytes exceeds the maximum frame size of {} bytes",
+                    payload.len(),
+                    max_bytes
+                ),
+            )
+        })?;
+    let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + payload.len());
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(payload);
+    Ok(frame)
+}
+
+/// Removes the first complete frame from the buffer and returns its payload, if there is one.
+/// Fails if the length announced by the peer exceeds `max_bytes`.
+fn next_frame(buffer: &mut BytesMut, max_bytes: usize) -> io::Result<Option<Bytes>> {
+    if buffer.len() < FRAME_HEADER_SIZE {
+        return Ok(None);
+    }
+    let len = (&buffer[..FRAME_HEADER_SIZE]).get_u32() as usize;
+    if len > max_bytes {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Received a frame of {} bytes exceeding the maximum frame size of {} bytes",
+                len, max_bytes
+            ),
+        ));
+    }
+    if buffer.len() < FRAME_HEADER_SIZE + len {
+        return Ok(None);
+    }
+    buffer.advance(FRAME_HEADER_SIZE);
+    Ok(Some(buffer.split_to(len).freeze()))
 }
 
 /// System to receive messages from all open `TcpStream`s.
@@ -225,12 +577,19 @@ pub struct TcpNetworkRecvSystem;
 impl<'s> System<'s> for TcpNetworkRecvSystem {
     type SystemData = (
         Write<'s, TcpNetworkResource>,
+        Write<'s, FragmentReassembler>,
+        Write<'s, ReceiveGate>,
         Write<'s, EventChannel<NetworkSimulationEvent>>,
     );
 
-    fn run(&mut self, (mut net, mut event_channel): Self::SystemData) {
+    fn run(&mut self, (mut net, mut reassembler, mut gate, mut event_channel): Self::SystemData) {
+        reassembler.sweep(Instant::now(), &mut event_channel);
+        gate.release(&mut event_channel);
         let resource = net.deref_mut();
-        for (_, (active, stream)) in resource.streams.iter_mut() {
+        for (addr, (active, stream)) in resource.streams.iter_mut() {
+            if resource.kicked.contains(addr) {
+                continue;
+            }
             // If we can't get a peer_addr, there is likely something pretty wrong with the
             // connection so we'll mark it inactive.
             let peer_addr = match stream.peer_addr() {
@@ -246,11 +605,45 @@ impl<'s> System<'s> for TcpNetworkRecvSystem {
                 match stream.read(&mut resource.recv_buffer) {
                     Ok(recv_len) => {
                         if recv_len > 0 {
-                            let event = NetworkSimulationEvent::Message(
-                                peer_addr,
-                                Bytes::copy_from_slice(&resource.recv_buffer[..recv_len]),
-                            );
-                            event_channel.single_write(event);
+                            let received = &resource.recv_buffer[..recv_len];
+                            if resource.framing {
+                                let buffer = resource.frame_buffers.entry(peer_addr).or_default();
+                                buffer.extend_from_slice(received);
+                                loop {
+                                    match next_frame(buffer, resource.max_frame_bytes) {
+                                        Ok(Some(bytes)) => wire::receive(
+                                            peer_addr,
+                                            bytes,
+                                            &mut reassembler,
+                                            &mut gate,
+                                            &mut event_channel,
+                                        ),
+                                        Ok(None) => break,
+                                        // The stream can't be resynchronized past a bad frame
+                                        Err(e) => {
+                                            event_channel
+                                                .single_write(NetworkSimulationEvent::RecvError(e));
+                                            buffer.clear();
+                                            *active = false;
+                                            break;
+                                        }
+                                    }
+                                }
+                                if !*active {
+                                    break;
+                                }
+                            } else {
+                                // Without framing there is no header, the stream only carries
+                                // application payloads.
+                                wire::deliver(
+                                    peer_addr,
+                                    Header::default(),
+                                    Bytes::copy_from_slice(received),
+                                    &mut reassembler,
+                                    &mut gate,
+                                    &mut event_channel,
+                                );
+                            }
                         } else {
                             *active = false;
                             break;
@@ -261,7 +654,8 @@ impl<'s> System<'s> for TcpNetworkRecvSystem {
                             io::ErrorKind::ConnectionReset => {
                                 *active = false;
                             }
-                            io::ErrorKind::WouldBlock => {}
+                            // Blocking streams report an elapsed read timeout as either
+                            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {}
                             _ => {
                                 event_channel.single_write(NetworkSimulationEvent::RecvError(e));
                             }
@@ -278,6 +672,21 @@ pub struct TcpNetworkResource {
     listener: Option<TcpListener>,
     streams: HashMap<SocketAddr, (bool, TcpStream)>,
     recv_buffer: Vec<u8>,
+    linger: Option<Duration>,
+    framing: bool,
+    frame_buffers: HashMap<SocketAddr, BytesMut>,
+    outbound: HashSet<SocketAddr>,
+    /// Streams of kicked peers, shut down once their send queue has drained.
+    kicked: HashSet<SocketAddr>,
+    reconnect_backoff: Option<Duration>,
+    reconnects: HashMap<SocketAddr, (Instant, Duration)>,
+    /// Addresses whose reconnection was cancelled, not dialed for queued messages anymore.
+    cancelled: HashSet<SocketAddr>,
+    send_queues: HashMap<SocketAddr, VecDeque<PendingWrite>>,
+    max_send_queue_bytes: Option<usize>,
+    max_frame_bytes: usize,
+    read_timeout: Option<Duration>,
+    pending_events: Vec<NetworkSimulationEvent>,
 }
 
 impl TcpNetworkResource {
@@ -286,9 +695,57 @@ impl TcpNetworkResource {
             listener,
             streams: HashMap::new(),
             recv_buffer: vec![0; recv_buffer_size_bytes],
+            linger: None,
+            framing: false,
+            frame_buffers: HashMap::new(),
+            outbound: HashSet::new(),
+            kicked: HashSet::new(),
+            reconnect_backoff: None,
+            reconnects: HashMap::new(),
+            cancelled: HashSet::new(),
+            send_queues: HashMap::new(),
+            max_send_queue_bytes: None,
+            max_frame_bytes: DEFAULT_MAX_FRAME_BYTES,
+            read_timeout: None,
+            pending_events: Vec::new(),
         }
     }
 
+    /// Creates a new `TcpNetworkResource` accepting connections on an already bound listening
+    /// socket passed as a file descriptor, e.g. by systemd socket activation. The socket is
+    /// switched to non-blocking mode.
+    ///
+    /// # Safety
+    ///
+    /// The file descriptor must be an open listening TCP socket owned by nothing else, since it is
+    /// closed along with the listener.
+    #[cfg(unix)]
+    pub unsafe fn from_raw_listener_fd(
+        fd: RawFd,
+        recv_buffer_size_bytes: usize,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::from_raw_fd(fd);
+        listener.set_nonblocking(true)?;
+        Ok(Self::new(Some(listener), recv_buffer_size_bytes))
+    }
+
+    /// Creates a new `TcpNetworkResource` accepting connections on an already bound listening
+    /// socket passed as a raw socket. The socket is switched to non-blocking mode.
+    ///
+    /// # Safety
+    ///
+    /// The raw socket must be an open listening TCP socket owned by nothing else, since it is
+    /// closed along with the listener.
+    #[cfg(windows)]
+    pub unsafe fn from_raw_socket(
+        socket: RawSocket,
+        recv_buffer_size_bytes: usize,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::from_raw_socket(socket);
+        listener.set_nonblocking(true)?;
+        Ok(Self::new(Some(listener), recv_buffer_size_bytes))
+    }
+
     /// Returns an immutable reference to the listener if there is one configured.
     pub fn get(&self) -> Option<&TcpListener> {
         self.listener.as_ref()
@@ -299,24 +756,218 @@ impl TcpNetworkResource {
         self.listener.as_mut()
     }
 
+    /// Returns the local address of the listener, or `None` if there is no listener configured or
+    /// its address can't be read.
+    pub fn listener_addr(&self) -> Option<SocketAddr> {
+        self.listener
+            .as_ref()
+            .and_then(|listener| listener.local_addr().ok())
+    }
+
     /// Sets the bound listener to the `TcpNetworkResource`.
     pub fn set_listener(&mut self, listener: TcpListener) {
         self.listener = Some(listener);
     }
 
+    /// Binds a new listener to the given address and swaps it in place of the current one. The
+    /// connections pending on the current listener are accepted first and the existing streams are
+    /// kept, so only new connections arrive on the new address. If binding fails the current
+    /// listener is kept. A pending connection which can't be configured is closed and reported
+    /// with a `NetworkSimulationEvent::ConnectionError` instead of failing the rebind. These
+    /// events and a `NetworkSimulationEvent::ListenerRebound` are emitted by the
+    /// `TcpConnectionListenerSystem`.
+    pub fn rebind_listener(&mut self, new_addr: SocketAddr) -> io::Result<()> {
+        let listener = TcpListener::bind(new_addr)?;
+        listener.set_nonblocking(true)?;
+        let new = listener.local_addr()?;
+
+        // Each pending connection is fully configured before it is kept
+        let mut accepted = Vec::new();
+        if let Some(ref old_listener) = self.listener {
+            while let Ok((stream, addr)) = old_listener.accept() {
+                let configured = set_blocking_mode(&stream, self.read_timeout)
+                    .and_then(|_| stream.set_nodelay(true));
+                match configured {
+                    Ok(()) => accepted.push((addr, apply_linger(stream, self.linger))),
+                    Err(e) => self
+                        .pending_events
+                        .push(NetworkSimulationEvent::ConnectionError(e, Some(addr))),
+                }
+            }
+        }
+
+        let old = self.listener_addr();
+        self.listener = Some(listener);
+        for (addr, stream) in accepted {
+            self.streams.insert(addr, (true, stream));
+            self.pending_events
+                .push(NetworkSimulationEvent::Connect(addr));
+        }
+        self.pending_events
+            .push(NetworkSimulationEvent::ListenerRebound { old, new });
+        Ok(())
+    }
+
     /// Drops the listener from the `TcpNetworkResource`.
     pub fn drop_listener(&mut self) {
         self.listener = None;
     }
 
+    /// Returns the `SO_LINGER` duration applied to new streams, if any.
+    pub fn linger(&self) -> Option<Duration> {
+        self.linger
+    }
+
+    /// Sets the `SO_LINGER` duration applied to streams connected or accepted from now on. With a
+    /// duration, closing a stream gives queued data up to that long to be flushed, and a zero
+    /// duration closes it immediately discarding unsent data. `None` keeps the OS default.
+    pub fn set_linger(&mut self, linger: Option<Duration>) {
+        self.linger = linger;
+    }
+
+    /// Returns true if messages are framed.
+    pub fn framing(&self) -> bool {
+        self.framing
+    }
+
+    /// Enables or disables length-prefix framing. With framing, every message is prefixed with its
+    /// length as a big-endian u32 and received bytes are buffered per stream until a whole message
+    /// has arrived, so each `NetworkSimulationEvent::Message` matches a message that was sent.
+    /// Without framing, whatever bytes were read are emitted as is. Both ends must agree.
+    pub fn set_framing(&mut self, framing: bool) {
+        self.framing = framing;
+    }
+
+    /// Returns the maximum number of bytes waiting to be written on a stream, if bounded.
+    pub fn max_send_queue_bytes(&self) -> Option<usize> {
+        self.max_send_queue_bytes
+    }
+
+    /// Bounds the number of bytes waiting to be written on each stream. Messages the socket
+    /// doesn't accept right away are queued and written on the following ticks; when a message
+    /// would exceed the bound, a `NetworkSimulationEvent::SendError` is emitted and the stream is
+    /// marked inactive so a stuck peer can't grow the queue forever. `None` leaves it unbounded.
+    pub fn set_max_send_queue_bytes(&mut self, max_bytes: Option<usize>) {
+        self.max_send_queue_bytes = max_bytes;
+    }
+
+    /// Returns the maximum size of a frame, header of the transports included.
+    pub fn max_frame_bytes(&self) -> usize {
+        self.max_frame_bytes
+    }
+
+    /// Bounds the size of a frame, header of the transports included, 1 MiB by default. Sending a
+    /// larger message fails with a `NetworkSimulationEvent::SendError`. A peer announcing a larger
+    /// frame can't be trusted to stay in sync, so a `NetworkSimulationEvent::RecvError` is emitted
+    /// and its stream is marked inactive instead of buffering the frame.
+    pub fn set_max_frame_bytes(&mut self, max_bytes: usize) {
+        self.max_frame_bytes = max_bytes;
+    }
+
+    /// Returns the read timeout of blocking streams, or `None` if the streams are non-blocking.
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.read_timeout
+    }
+
+    /// Switches streams connected or accepted from now on between non-blocking mode, the default,
+    /// and blocking mode with the given read timeout. A zero timeout, which the OS rejects, is
+    /// treated as `None`. In blocking mode the `TcpNetworkRecvSystem` waits up to the timeout for
+    /// more data on every stream each tick and writes block until the socket accepts them, which
+    /// suits tools and tests running outside of a game loop. The listener stays non-blocking.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_timeout = timeout.filter(|timeout| *timeout > Duration::from_secs(0));
+    }
+
+    /// Returns the number of bytes waiting to be written on the stream to the given address.
+    pub fn queued_bytes(&self, addr: SocketAddr) -> usize {
+        self.send_queues.get(&addr).map_or(0, |queue| {
+            queue.iter().map(|pending| pending.remaining().len()).sum()
+        })
+    }
+
+    /// Returns the delay before the first reconnection attempt, or `None` if automatic
+    /// reconnection is disabled.
+    pub fn reconnect_backoff(&self) -> Option<Duration> {
+        self.reconnect_backoff
+    }
+
+    /// Enables or disables automatic reconnection. When enabled, a reconnection is scheduled after
+    /// the given delay whenever a stream this end connected is lost or a connection attempt fails.
+    /// The delay doubles after every failed attempt, up to 30 seconds, and a
+    /// `NetworkSimulationEvent::Connect` is emitted once reconnected.
+    pub fn set_reconnect_backoff(&mut self, backoff: Option<Duration>) {
+        self.reconnect_backoff = backoff;
+    }
+
+    /// Schedules a reconnection to the given address after the reconnect backoff, or after one
+    /// second if automatic reconnection is disabled. Does nothing if one is already scheduled.
+    pub fn schedule_reconnect(&mut self, addr: SocketAddr) {
+        self.cancelled.remove(&addr);
+        let backoff = self.reconnect_backoff.unwrap_or(DEFAULT_RECONNECT_BACKOFF);
+        self.reconnects
+            .entry(addr)
+            .or_insert_with(|| (Instant::now() + backoff, backoff));
+    }
+
+    /// Returns the addresses with a scheduled reconnection.
+    pub fn pending_reconnects(&self) -> Vec<SocketAddr> {
+        self.reconnects.keys().copied().collect()
+    }
+
+    /// Cancels the scheduled reconnection to the given address. Returns false if there was none.
+    ///
+    /// Messages queued for the address don't dial it either until `schedule_reconnect` is called
+    /// again.
+    pub fn cancel_reconnect(&mut self, addr: SocketAddr) -> bool {
+        self.outbound.remove(&addr);
+        self.cancelled.insert(addr);
+        self.reconnects.remove(&addr).is_some()
+    }
+
     /// Returns a tuple of an active TcpStream and whether ot not that stream is active
     pub fn get_stream(&mut self, addr: SocketAddr) -> Option<&mut (bool, TcpStream)> {
         self.streams.get_mut(&addr)
     }
 
+    /// Kicks the peer at the given address. With framing enabled, the reason is queued on the
+    /// stream as a control message, after the messages already queued, and surfaces on the peer
+    /// as a `NetworkSimulationEvent::Kicked`; an unframed stream carries no header, so the peer
+    /// only sees the stream close. Nothing else is sent or received on the stream, which the
+    /// `TcpNetworkSendSystem` shuts down once its queue has drained. A
+    /// `NetworkSimulationEvent::Disconnect` is then emitted by the `TcpStreamManagementSystem`.
+    pub fn kick(&mut self, addr: SocketAddr, reason: &str) -> io::Result<()> {
+        if !self.streams.contains_key(&addr) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "No stream to the given address",
+            ));
+        }
+        if self.framing && !self.kicked.contains(&addr) {
+            let kick = wire::encode_control(&Control::Kick {
+                reason: reason.to_owned(),
+            });
+            let bytes = frame(&kick, self.max_frame_bytes)?;
+            self.send_queues
+                .entry(addr)
+                .or_default()
+                .push_back(PendingWrite {
+                    message: None,
+                    bytes,
+                    written: 0,
+                });
+        }
+        self.outbound.remove(&addr);
+        self.kicked.insert(addr);
+        Ok(())
+    }
+
     /// Drops the stream with the given `SocketAddr`. This will be called when a peer seems to have
     /// been disconnected
     pub fn drop_stream(&mut self, addr: SocketAddr) -> Option<(bool, TcpStream)> {
+        self.kicked.remove(&addr);
+        self.frame_buffers.remove(&addr);
+        self.send_queues.remove(&addr);
+        self.outbound.remove(&addr);
         self.streams.remove(&addr)
     }
 }
@@ -327,6 +978,880 @@ impl Default for TcpNetworkResource {
             listener: None,
             streams: HashMap::new(),
             recv_buffer: Vec::new(),
+            linger: None,
+            framing: false,
+            frame_buffers: HashMap::new(),
+            outbound: HashSet::new(),
+            kicked: HashSet::new(),
+            reconnect_backoff: None,
+            reconnects: HashMap::new(),
+            cancelled: HashSet::new(),
+            send_queues: HashMap::new(),
+            max_send_queue_bytes: None,
+            max_frame_bytes: DEFAULT_MAX_FRAME_BYTES,
+            read_timeout: None,
+            pending_events: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::requirements::UrgencyRequirement;
+    use amethyst_core::ecs::{RunNow, WorldExt};
+    use std::thread;
+
+    #[test]
+    fn test_linger_is_applied_to_accepted_stream() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let listener_addr = listener.local_addr().unwrap();
+        let mut resource = TcpNetworkResource::new(Some(listener), 1024);
+        resource.set_linger(Some(Duration::from_secs(3)));
+
+        let mut world = World::new();
+        world.insert(resource);
+        world.insert(EventChannel::<NetworkSimulationEvent>::new());
+
+        let client = TcpStream::connect(listener_addr).unwrap();
+        let client_addr = client.local_addr().unwrap();
+        for _ in 0..100 {
+            TcpConnectionListenerSystem.run_now(&world);
+            if world
+                .fetch_mut::<TcpNetworkResource>()
+                .get_stream(client_addr)
+                .is_some()
+            {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let mut resource = world.fetch_mut::<TcpNetworkResource>();
+        let (_, stream) = resource.get_stream(client_addr).unwrap();
+        let socket = Socket::from(stream.try_clone().unwrap());
+        assert_eq!(socket.linger().unwrap(), Some(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn test_blocking_streams_deliver_messages_within_read_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let listener_addr = listener.local_addr().unwrap();
+        let mut resource = TcpNetworkResource::new(Some(listener), 1024);
+        resource.set_read_timeout(Some(Duration::from_secs(0)));
+        assert_eq!(resource.read_timeout(), None);
+        resource.set_read_timeout(Some(Duration::from_millis(20)));
+
+        let mut world = World::new();
+        world.insert(resource);
+        world.insert(EventChannel::<NetworkSimulationEvent>::new());
+        world.insert(ReceiveGate::new());
+        world.insert(FragmentReassembler::default());
+        let mut reader = world
+            .fetch_mut::<EventChannel<NetworkSimulationEvent>>()
+            .register_reader();
+
+        let mut client = TcpStream::connect(listener_addr).unwrap();
+        let client_addr = client.local_addr().unwrap();
+        for _ in 0..100 {
+            TcpConnectionListenerSystem.run_now(&world);
+            if world
+                .fetch_mut::<TcpNetworkResource>()
+                .get_stream(client_addr)
+                .is_some()
+            {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        {
+            let mut resource = world.fetch_mut::<TcpNetworkResource>();
+            let (_, stream) = resource.get_stream(client_addr).unwrap();
+            assert_eq!(
+                stream.read_timeout().unwrap(),
+                Some(Duration::from_millis(20))
+            );
+        }
+
+        // Without data the read times out instead of hanging
+        let start = Instant::now();
+        TcpNetworkRecvSystem.run_now(&world);
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        client.write_all(b"hello").unwrap();
+        let start = Instant::now();
+        TcpNetworkRecvSystem.run_now(&world);
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let channel = world.fetch::<EventChannel<NetworkSimulationEvent>>();
+        let events = channel
+            .read(&mut reader)
+            .map(|event| format!("{:?}", event))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                format!("{:?}", NetworkSimulationEvent::Connect(client_addr)),
+                format!(
+                    "{:?}",
+                    NetworkSimulationEvent::Message(
+                        client_addr,
+                        Bytes::from_static(b"hello"),
+                        None
+                    )
+                ),
+            ]
+        );
+        assert!(
+            world
+                .fetch_mut::<TcpNetworkResource>()
+                .get_stream(client_addr)
+                .unwrap()
+                .0
+        );
+    }
+
+    #[test]
+    fn test_listener_addr_returns_bound_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let bound_addr = listener.local_addr().unwrap();
+        let resource = TcpNetworkResource::new(Some(listener), 1024);
+
+        let addr = resource.listener_addr().unwrap();
+        assert_eq!(addr, bound_addr);
+        assert_ne!(addr.port(), 0);
+    }
+
+    #[test]
+    fn test_rebind_listener_keeps_existing_streams() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let old_addr = listener.local_addr().unwrap();
+
+        let mut world = World::new();
+        world.insert(TcpNetworkResource::new(Some(listener), 1024));
+        world.insert(EventChannel::<NetworkSimulationEvent>::new());
+        let mut reader = world
+            .fetch_mut::<EventChannel<NetworkSimulationEvent>>()
+            .register_reader();
+
+        let accept = |client_addr| {
+            for _ in 0..100 {
+                TcpConnectionListenerSystem.run_now(&world);
+                if world
+                    .fetch_mut::<TcpNetworkResource>()
+                    .get_stream(client_addr)
+                    .is_some()
+                {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+        };
+
+        let mut old_client = TcpStream::connect(old_addr).unwrap();
+        let old_client_addr = old_client.local_addr().unwrap();
+        accept(old_client_addr);
+
+        world
+            .fetch_mut::<TcpNetworkResource>()
+            .rebind_listener("127.0.0.1:0".parse().unwrap())
+            .unwrap();
+        let new_addr = world.fetch::<TcpNetworkResource>().listener_addr().unwrap();
+        assert_ne!(new_addr, old_addr);
+        assert!(TcpStream::connect(old_addr).is_err());
+
+        let new_client = TcpStream::connect(new_addr).unwrap();
+        let new_client_addr = new_client.local_addr().unwrap();
+        accept(new_client_addr);
+
+        let mut resource = world.fetch_mut::<TcpNetworkResource>();
+        assert!(resource.get_stream(new_client_addr).is_some());
+        let (active, stream) = resource.get_stream(old_client_addr).unwrap();
+        assert!(*active);
+        stream.write_all(b"still here").unwrap();
+        let mut buf = [0; 10];
+        old_client.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"still here");
+
+        let events = world
+            .fetch::<EventChannel<NetworkSimulationEvent>>()
+            .read(&mut reader)
+            .map(|event| format!("{:?}", event))
+            .collect::<Vec<_>>();
+        assert!(events.contains(&format!(
+            "{:?}",
+            NetworkSimulationEvent::ListenerRebound {
+                old: Some(old_addr),
+                new: new_addr
+            }
+        )));
+    }
+
+    #[test]
+    fn test_kicked_peer_receives_reason_before_disconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let server_addr = listener.local_addr().unwrap();
+        let mut server_resource = TcpNetworkResource::new(Some(listener), 1024);
+        server_resource.set_framing(true);
+        let mut server = World::new();
+        server.insert(server_resource);
+        server.insert(EventChannel::<NetworkSimulationEvent>::new());
+        server.insert(TransportResource::new());
+        server.insert(NetworkSimulationTime::default());
+        server.insert(DropStats::default());
+        let mut server_reader = server
+            .fetch_mut::<EventChannel<NetworkSimulationEvent>>()
+            .register_reader();
+
+        let client_stream = TcpStream::connect(server_addr).unwrap();
+        client_stream.set_nonblocking(true).unwrap();
+        let client_addr = client_stream.local_addr().unwrap();
+        let mut client_resource = TcpNetworkResource::new(None, 1024);
+        client_resource.set_framing(true);
+        client_resource
+            .streams
+            .insert(server_addr, (true, client_stream));
+        let mut client = World::new();
+        client.insert(client_resource);
+        client.insert(EventChannel::<NetworkSimulationEvent>::new());
+        client.insert(ReceiveGate::new());
+        client.insert(FragmentReassembler::default());
+        client.insert(TransportResource::new());
+        let mut reader = client
+            .fetch_mut::<EventChannel<NetworkSimulationEvent>>()
+            .register_reader();
+
+        for _ in 0..100 {
+            TcpConnectionListenerSystem.run_now(&server);
+            if server
+                .fetch_mut::<TcpNetworkResource>()
+                .get_stream(client_addr)
+                .is_some()
+            {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        // Messages queued before the kick are still delivered, the ones sent after are dropped
+        server
+            .fetch_mut::<TransportResource>()
+            .send_immediate(client_addr, b"bye");
+        TcpNetworkSendSystem.run_now(&server);
+        server
+            .fetch_mut::<TcpNetworkResource>()
+            .kick(client_addr, "cheating")
+            .unwrap();
+        server
+            .fetch_mut::<TransportResource>()
+            .send_immediate(client_addr, b"late");
+        TcpNetworkSendSystem.run_now(&server);
+        TcpStreamManagementSystem.run_now(&server);
+        assert!(server
+            .fetch_mut::<TcpNetworkResource>()
+            .get_stream(client_addr)
+            .is_none());
+        assert_eq!(
+            server.fetch::<DropStats>().count(DropReason::NotConnected),
+            1
+        );
+        assert!(server
+            .fetch::<EventChannel<NetworkSimulationEvent>>()
+            .read(&mut server_reader)
+            .any(|event| matches!(event, NetworkSimulationEvent::Disconnect(addr) if *addr == client_addr)));
+
+        let mut events = Vec::new();
+        for _ in 0..100 {
+            TcpNetworkRecvSystem.run_now(&client);
+            TcpStreamManagementSystem.run_now(&client);
+            events.extend(
+                client
+                    .fetch::<EventChannel<NetworkSimulationEvent>>()
+                    .read(&mut reader)
+                    .map(|event| format!("{:?}", event)),
+            );
+            if events.len() == 3 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(
+            events,
+            vec![
+                format!(
+                    "{:?}",
+                    NetworkSimulationEvent::Message(server_addr, Bytes::from_static(b"bye"), None)
+                ),
+                format!(
+                    "{:?}",
+                    NetworkSimulationEvent::Kicked {
+                        addr: server_addr,
+                        reason: "cheating".to_owned()
+                    }
+                ),
+                format!("{:?}", NetworkSimulationEvent::Disconnect(server_addr)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_oversized_frames_are_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut sender = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (receiver, sender_addr) = listener.accept().unwrap();
+        receiver.set_nonblocking(true).unwrap();
+        let mut resource = TcpNetworkResource::new(None, 64);
+        resource.set_framing(true);
+        resource.set_max_frame_bytes(16);
+        resource.streams.insert(sender_addr, (true, receiver));
+        let mut drops = DropStats::default();
+        let mut channel = EventChannel::<NetworkSimulationEvent>::new();
+        let mut reader = channel.register_reader();
+
+        // Sending a message larger than a frame fails instead of truncating its length
+        let message = Message::new(
+            sender_addr,
+            &[0; 32],
+            DeliveryRequirement::ReliableOrdered(None),
+            UrgencyRequirement::Immediate,
+        );
+        write_message(message, &mut resource, &mut drops, &mut channel);
+        assert_eq!(drops.count(DropReason::SendFailed), 1);
+        assert!(channel
+            .read(&mut reader)
+            .any(|event| matches!(event, NetworkSimulationEvent::SendError(..))));
+
+        // A peer announcing a larger frame is disconnected before it is buffered
+        sender.write_all(&u32::MAX.to_be_bytes()).unwrap();
+        let mut world = World::new();
+        world.insert(resource);
+        world.insert(channel);
+        world.insert(ReceiveGate::new());
+        world.insert(FragmentReassembler::default());
+        for _ in 0..100 {
+            TcpNetworkRecvSystem.run_now(&world);
+            if !world
+                .fetch_mut::<TcpNetworkResource>()
+                .get_stream(sender_addr)
+                .unwrap()
+                .0
+            {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(
+            !world
+                .fetch_mut::<TcpNetworkResource>()
+                .get_stream(sender_addr)
+                .unwrap()
+                .0
+        );
+        assert!(world
+            .fetch::<EventChannel<NetworkSimulationEvent>>()
+            .read(&mut reader)
+            .any(|event| matches!(event, NetworkSimulationEvent::RecvError(e) if e.kind() == io::ErrorKind::InvalidData)));
+    }
+
+    #[test]
+    fn test_framed_messages_are_split_and_joined_across_reads() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut sender = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (receiver, sender_addr) = listener.accept().unwrap();
+        receiver.set_nonblocking(true).unwrap();
+
+        // A receive buffer smaller than a frame forces frames to span several reads
+        let mut resource = TcpNetworkResource::new(None, 8);
+        resource.set_framing(true);
+        resource.streams.insert(sender_addr, (true, receiver));
+        let mut world = World::new();
+        world.insert(resource);
+        world.insert(EventChannel::<NetworkSimulationEvent>::new());
+        world.insert(ReceiveGate::new());
+        world.insert(FragmentReassembler::default());
+        let mut reader = world
+            .fetch_mut::<EventChannel<NetworkSimulationEvent>>()
+            .register_reader();
+        let mut receive = |expected: usize| {
+            let mut messages = Vec::new();
+            for _ in 0..100 {
+                TcpNetworkRecvSystem.run_now(&world);
+                messages.extend(
+                    world
+                        .fetch::<EventChannel<NetworkSimulationEvent>>()
+                        .read(&mut reader)
+                        .filter_map(|event| match event {
+                            NetworkSimulationEvent::Message(_, payload, _) => Some(payload.clone()),
+                            _ => None,
+                        }),
+                );
+                if messages.len() >= expected {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+            messages
+        };
+
+        let large = (0..20).collect::<Vec<u8>>();
+        let large_frame = frame(&wire::encode_message(&large), DEFAULT_MAX_FRAME_BYTES).unwrap();
+        let mut bytes = frame(&wire::encode_message(b"one"), DEFAULT_MAX_FRAME_BYTES).unwrap();
+        bytes.extend(frame(&wire::encode_message(b"two"), DEFAULT_MAX_FRAME_BYTES).unwrap());
+        bytes.extend_from_slice(&large_frame[..10]);
+        sender.write_all(&bytes).unwrap();
+        assert_eq!(receive(2), vec![&b"one"[..], &b"two"[..]]);
+
+        sender.write_all(&large_frame[10..]).unwrap();
+        assert_eq!(receive(1), vec![Bytes::from(large)]);
+    }
+
+    #[test]
+    fn test_sequenced_channel_drops_superseded_messages() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let sender = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        sender.set_nonblocking(true).unwrap();
+        let (receiver, sender_addr) = listener.accept().unwrap();
+        receiver.set_nonblocking(true).unwrap();
+        let receiver_addr = sender.peer_addr().unwrap();
+
+        let mut sender_resource = TcpNetworkResource::new(None, 1024);
+        sender_resource.set_framing(true);
+        sender_resource
+            .streams
+            .insert(receiver_addr, (true, sender));
+        let mut sending = World::new();
+        sending.insert(sender_resource);
+        sending.insert(TransportResource::new());
+        sending.insert(NetworkSimulationTime::default());
+        sending.insert(DropStats::default());
+        sending.insert(EventChannel::<NetworkSimulationEvent>::new());
+
+        let mut receiver_resource = TcpNetworkResource::new(None, 1024);
+        receiver_resource.set_framing(true);
+        receiver_resource
+            .streams
+            .insert(sender_addr, (true, receiver));
+        let mut receiving = World::new();
+        receiving.insert(receiver_resource);
+        receiving.insert(EventChannel::<NetworkSimulationEvent>::new());
+        receiving.insert(ReceiveGate::new());
+        receiving.insert(FragmentReassembler::default());
+        let mut reader = receiving
+            .fetch_mut::<EventChannel<NetworkSimulationEvent>>()
+            .register_reader();
+
+        let ordered = DeliveryRequirement::ReliableOrdered(Some(1));
+        let sequenced = DeliveryRequirement::ReliableSequenced(Some(2));
+        {
+            let mut transport = sending.fetch_mut::<TransportResource>();
+            for i in 0..3u8 {
+                for &(delivery, tag) in [(ordered, b'o'), (sequenced, b's')].iter() {
+                    transport.send_with_requirements(
+                        receiver_addr,
+                        &[tag, i],
+                        delivery,
+                        UrgencyRequirement::Immediate,
+                    );
+                }
+            }
+        }
+        TcpNetworkSendSystem.run_now(&sending);
+
+        let mut received = Vec::new();
+        for _ in 0..100 {
+            TcpNetworkRecvSystem.run_now(&receiving);
+            received.extend(
+                receiving
+                    .fetch::<EventChannel<NetworkSimulationEvent>>()
+                    .read(&mut reader)
+                    .filter_map(|event| match event {
+                        NetworkSimulationEvent::Message(_, payload, _) => Some(payload.to_vec()),
+                        _ => None,
+                    }),
+            );
+            if received.len() >= 4 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        let channel = |tag| {
+            received
+                .iter()
+                .filter(|payload| payload[0] == tag)
+                .map(|payload| payload[1])
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(channel(b'o'), vec![0, 1, 2]);
+        assert_eq!(channel(b's'), vec![2]);
+    }
+
+    #[test]
+    fn test_request_carries_correlation_id_only_with_framing() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let sender = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        sender.set_nonblocking(true).unwrap();
+        let (receiver, sender_addr) = listener.accept().unwrap();
+        receiver.set_nonblocking(true).unwrap();
+        let receiver_addr = sender.peer_addr().unwrap();
+
+        let mut sender_resource = TcpNetworkResource::new(None, 1024);
+        sender_resource.set_framing(true);
+        sender_resource
+            .streams
+            .insert(receiver_addr, (true, sender));
+        let mut sending = World::new();
+        sending.insert(sender_resource);
+        sending.insert(TransportResource::new());
+        sending.insert(NetworkSimulationTime::default());
+        sending.insert(DropStats::default());
+        sending.insert(EventChannel::<NetworkSimulationEvent>::new());
+        let mut sending_reader = sending
+            .fetch_mut::<EventChannel<NetworkSimulationEvent>>()
+            .register_reader();
+
+        let mut receiver_resource = TcpNetworkResource::new(None, 1024);
+        receiver_resource.set_framing(true);
+        receiver_resource
+            .streams
+            .insert(sender_addr, (true, receiver));
+        let mut receiving = World::new();
+        receiving.insert(receiver_resource);
+        receiving.insert(EventChannel::<NetworkSimulationEvent>::new());
+        receiving.insert(ReceiveGate::new());
+        receiving.insert(FragmentReassembler::default());
+        let mut reader = receiving
+            .fetch_mut::<EventChannel<NetworkSimulationEvent>>()
+            .register_reader();
+
+        let id = sending
+            .fetch_mut::<TransportResource>()
+            .request(
+                receiver_addr,
+                b"ping",
+                DeliveryRequirement::ReliableOrdered(None),
+            )
+            .unwrap();
+        TcpNetworkSendSystem.run_now(&sending);
+        let mut received = None;
+        for _ in 0..100 {
+            TcpNetworkRecvSystem.run_now(&receiving);
+            received = receiving
+                .fetch::<EventChannel<NetworkSimulationEvent>>()
+                .read(&mut reader)
+                .find_map(|event| match event {
+                    NetworkSimulationEvent::Message(_, payload, correlation_id) => {
+                        Some((payload.clone(), *correlation_id))
+                    }
+                    _ => None,
+                });
+            if received.is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(received, Some((Bytes::from_static(b"ping"), Some(id))));
+
+        // Without framing there is no header to carry the id
+        sending.fetch_mut::<TcpNetworkResource>().set_framing(false);
+        sending
+            .fetch_mut::<TransportResource>()
+            .request(
+                receiver_addr,
+                b"ping",
+                DeliveryRequirement::ReliableOrdered(None),
+            )
+            .unwrap();
+        TcpNetworkSendSystem.run_now(&sending);
+        assert_eq!(
+            sending.fetch::<DropStats>().count(DropReason::SendFailed),
+            1
+        );
+        assert!(sending
+            .fetch::<EventChannel<NetworkSimulationEvent>>()
+            .read(&mut sending_reader)
+            .any(|event| matches!(event, NetworkSimulationEvent::SendError(..))));
+    }
+
+    #[test]
+    fn test_unframed_stream_drops_fragmented_messages() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let sender = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let receiver_addr = sender.peer_addr().unwrap();
+        let mut resource = TcpNetworkResource::new(None, 1024);
+        resource.streams.insert(receiver_addr, (true, sender));
+        let mut drops = DropStats::default();
+        let mut channel = EventChannel::<NetworkSimulationEvent>::new();
+        let mut reader = channel.register_reader();
+
+        let mut transport = TransportResource::new();
+        transport
+            .send_fragmented(
+                receiver_addr,
+                &[42; 64],
+                16,
+                DeliveryRequirement::ReliableOrdered(None),
+                UrgencyRequirement::Immediate,
+            )
+            .unwrap();
+        for message in transport.drain_messages(|_| true) {
+            write_message(message, &mut resource, &mut drops, &mut channel);
+        }
+        assert_eq!(drops.count(DropReason::SendFailed), 4);
+        assert_eq!(channel.read(&mut reader).count(), 4);
+        assert!(!resource.send_queues.contains_key(&receiver_addr));
+    }
+
+    #[test]
+    fn test_cancelled_reconnect_is_not_attempted() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut resource = TcpNetworkResource::new(None, 1024);
+        resource.set_reconnect_backoff(Some(Duration::from_millis(0)));
+        resource.schedule_reconnect(addr);
+        assert_eq!(resource.pending_reconnects(), vec![addr]);
+        assert!(resource.cancel_reconnect(addr));
+        assert!(resource.pending_reconnects().is_empty());
+        assert!(!resource.cancel_reconnect(addr));
+
+        let mut world = World::new();
+        world.insert(resource);
+        // A queued message doesn't dial the cancelled address either
+        let mut transport = TransportResource::new();
+        transport.send_with_requirements(
+            addr,
+            b"test",
+            DeliveryRequirement::ReliableOrdered(None),
+            UrgencyRequirement::OnTick,
+        );
+        world.insert(transport);
+        world.insert(EventChannel::<NetworkSimulationEvent>::new());
+        for _ in 0..10 {
+            TcpStreamManagementSystem.run_now(&world);
+        }
+        assert_eq!(
+            listener.accept().unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+        assert!(world
+            .fetch_mut::<TcpNetworkResource>()
+            .get_stream(addr)
+            .is_none());
+
+        // Without cancelling, the scheduled reconnection connects
+        world
+            .fetch_mut::<TcpNetworkResource>()
+            .schedule_reconnect(addr);
+        TcpStreamManagementSystem.run_now(&world);
+        assert!(world
+            .fetch_mut::<TcpNetworkResource>()
+            .get_stream(addr)
+            .is_some());
+        assert!(world
+            .fetch::<TcpNetworkResource>()
+            .pending_reconnects()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_unwritten_bytes_are_kept_until_the_peer_reads() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let sender = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        sender.set_nonblocking(true).unwrap();
+        let (mut receiver, _) = listener.accept().unwrap();
+        receiver.set_nonblocking(true).unwrap();
+        let receiver_addr = sender.peer_addr().unwrap();
+
+        let mut resource = TcpNetworkResource::new(None, 1024);
+        resource.streams.insert(receiver_addr, (true, sender));
+        let mut world = World::new();
+        world.insert(resource);
+        world.insert(TransportResource::new());
+        world.insert(NetworkSimulationTime::default());
+        world.insert(DropStats::default());
+        world.insert(EventChannel::<NetworkSimulationEvent>::new());
+        let mut reader = world
+            .fetch_mut::<EventChannel<NetworkSimulationEvent>>()
+            .register_reader();
+
+        // Far more than the socket buffers can hold while the peer isn't reading
+        let payload = (0..16 * 1024 * 1024)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        world
+            .fetch_mut::<TransportResource>()
+            .send_immediate(receiver_addr, &payload);
+        TcpNetworkSendSystem.run_now(&world);
+        assert!(
+            world
+                .fetch::<TcpNetworkResource>()
+                .queued_bytes(receiver_addr)
+                > 0
+        );
+
+        let mut received = Vec::new();
+        let mut buffer = vec![0; 64 * 1024];
+        for _ in 0..10_000 {
+            TcpNetworkSendSystem.run_now(&world);
+            match receiver.read(&mut buffer) {
+                Ok(n) => received.extend_from_slice(&buffer[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(1))
+                }
+                Err(e) => panic!("{}", e),
+            }
+            if received.len() >= payload.len() {
+                break;
+            }
+        }
+        assert!(received == payload);
+        assert_eq!(
+            world
+                .fetch::<TcpNetworkResource>()
+                .queued_bytes(receiver_addr),
+            0
+        );
+        assert_eq!(
+            world
+                .fetch::<EventChannel<NetworkSimulationEvent>>()
+                .read(&mut reader)
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_dropped_messages_are_counted_by_reason() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let sender = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        sender.set_nonblocking(true).unwrap();
+        let (_receiver, _) = listener.accept().unwrap();
+        let receiver_addr = sender.peer_addr().unwrap();
+        let unknown_addr = "127.0.0.1:1".parse().unwrap();
+
+        let mut resource = TcpNetworkResource::new(None, 1024);
+        resource.set_max_send_queue_bytes(Some(8));
+        resource.streams.insert(receiver_addr, (true, sender));
+        let mut world = World::new();
+        world.insert(resource);
+        world.insert(TransportResource::new());
+        world.insert(NetworkSimulationTime::default());
+        world.insert(DropStats::default());
+        world.insert(EventChannel::<NetworkSimulationEvent>::new());
+
+        {
+            let mut transport = world.fetch_mut::<TransportResource>();
+            transport.send_immediate(unknown_addr, b"lost");
+            transport.send_immediate(unknown_addr, b"lost");
+            transport.send_immediate(receiver_addr, &[7; 16]);
         }
+        TcpNetworkSendSystem.run_now(&world);
+
+        let drops = world.fetch::<DropStats>();
+        assert_eq!(drops.count(DropReason::NotConnected), 2);
+        assert_eq!(drops.count(DropReason::QueueFull), 1);
+        assert_eq!(drops.count(DropReason::SendFailed), 0);
+        assert_eq!(drops.total(), 3);
+    }
+
+    #[test]
+    fn test_exceeding_send_queue_bound_marks_stream_inactive() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let sender = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        sender.set_nonblocking(true).unwrap();
+        let (_receiver, _) = listener.accept().unwrap();
+        let receiver_addr = sender.peer_addr().unwrap();
+
+        let mut resource = TcpNetworkResource::new(None, 1024);
+        resource.set_max_send_queue_bytes(Some(1024 * 1024));
+        resource.streams.insert(receiver_addr, (true, sender));
+        let mut world = World::new();
+        world.insert(resource);
+        world.insert(TransportResource::new());
+        world.insert(NetworkSimulationTime::default());
+        world.insert(DropStats::default());
+        world.insert(EventChannel::<NetworkSimulationEvent>::new());
+        let mut reader = world
+            .fetch_mut::<EventChannel<NetworkSimulationEvent>>()
+            .register_reader();
+
+        // The peer never reads, so the queue eventually fills up
+        let mut send_errors = 0;
+        for _ in 0..1000 {
+            world
+                .fetch_mut::<TransportResource>()
+                .send_immediate(receiver_addr, &[7; 64 * 1024]);
+            TcpNetworkSendSystem.run_now(&world);
+            send_errors += world
+                .fetch::<EventChannel<NetworkSimulationEvent>>()
+                .read(&mut reader)
+                .filter(|event| matches!(event, NetworkSimulationEvent::SendError(..)))
+                .count();
+            if send_errors > 0 {
+                break;
+            }
+        }
+        assert_eq!(send_errors, 1);
+        let mut resource = world.fetch_mut::<TcpNetworkResource>();
+        assert_eq!(resource.queued_bytes(receiver_addr), 0);
+        assert!(!resource.get_stream(receiver_addr).unwrap().0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_connections_are_accepted_on_adopted_listener_fd() {
+        use std::os::unix::io::IntoRawFd;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let listener_addr = listener.local_addr().unwrap();
+        let resource =
+            unsafe { TcpNetworkResource::from_raw_listener_fd(listener.into_raw_fd(), 1024) }
+                .unwrap();
+        assert_eq!(resource.listener_addr(), Some(listener_addr));
+
+        let mut world = World::new();
+        world.insert(resource);
+        world.insert(EventChannel::<NetworkSimulationEvent>::new());
+        let mut reader = world
+            .fetch_mut::<EventChannel<NetworkSimulationEvent>>()
+            .register_reader();
+
+        let client = TcpStream::connect(listener_addr).unwrap();
+        let client_addr = client.local_addr().unwrap();
+        let mut events = Vec::new();
+        for _ in 0..100 {
+            TcpConnectionListenerSystem.run_now(&world);
+            events.extend(
+                world
+                    .fetch::<EventChannel<NetworkSimulationEvent>>()
+                    .read(&mut reader)
+                    .map(|event| format!("{:?}", event)),
+            );
+            if !events.is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(
+            events,
+            vec![format!(
+                "{:?}",
+                NetworkSimulationEvent::Connect(client_addr)
+            )]
+        );
+        assert!(world
+            .fetch_mut::<TcpNetworkResource>()
+            .get_stream(client_addr)
+            .is_some());
+    }
+
+    #[test]
+    fn test_listener_addr_without_listener() {
+        let resource = TcpNetworkResource::new(None, 1024);
+        assert_eq!(resource.listener_addr(), None);
     }
 }
@@ -1,13 +1,18 @@
 //! Network systems implementation backed by the UDP network protocol.
 
 use crate::simulation::{
+    drop_stats::{DropReason, DropStats},
     events::NetworkSimulationEvent,
+    fragmentation::FragmentReassembler,
+    gate::ReceiveGate,
     requirements::DeliveryRequirement,
+    schedule::NetworkSchedule,
     timing::{NetworkSimulationTime, NetworkSimulationTimeSystem},
     transport::{
         TransportResource, NETWORK_RECV_SYSTEM_NAME, NETWORK_SEND_SYSTEM_NAME,
         NETWORK_SIM_TIME_SYSTEM_NAME,
     },
+    wire,
 };
 use amethyst_core::{
     bundle::SystemBundle,
@@ -16,7 +21,7 @@ use amethyst_core::{
 };
 use amethyst_error::Error;
 use bytes::Bytes;
-use std::{io, net::UdpSocket};
+use std::{io, net::UdpSocket, time::Instant};
 
 /// Use this network bundle to add the UDP transport layer to your game.
 pub struct UdpNetworkBundle {
@@ -39,23 +44,28 @@ impl<'a, 'b> SystemBundle<'a, 'b> for UdpNetworkBundle {
         world: &mut World,
         builder: &mut DispatcherBuilder<'_, '_>,
     ) -> Result<(), Error> {
-        builder.add(
+        let mut schedule = world.remove::<NetworkSchedule>().unwrap_or_default();
+        schedule.add(
+            builder,
             NetworkSimulationTimeSystem,
             NETWORK_SIM_TIME_SYSTEM_NAME,
             &[],
         );
-        builder.add(
+        schedule.add(
+            builder,
             UdpNetworkRecvSystem::with_buffer_capacity(self.recv_buffer_size_bytes),
             NETWORK_RECV_SYSTEM_NAME,
             &[NETWORK_SIM_TIME_SYSTEM_NAME],
         );
-        builder.add(
+        schedule.add(
+            builder,
             UdpNetworkSendSystem,
             NETWORK_SEND_SYSTEM_NAME,
             &[NETWORK_SIM_TIME_SYSTEM_NAME],
         );
 
         world.insert(UdpSocketResource::new(self.socket));
+        world.insert(schedule);
         Ok(())
     }
 }
@@ -67,16 +77,22 @@ impl<'s> System<'s> for UdpNetworkSendSystem {
         Write<'s, TransportResource>,
         Write<'s, UdpSocketResource>,
         Read<'s, NetworkSimulationTime>,
+        Write<'s, DropStats>,
         Write<'s, EventChannel<NetworkSimulationEvent>>,
     );
 
-    fn run(&mut self, (mut transport, mut socket, sim_time, mut channel): Self::SystemData) {
+    fn run(
+        &mut self,
+        (mut transport, mut socket, sim_time, mut drops, mut channel): Self::SystemData,
+    ) {
         if let Some(socket) = socket.get_mut() {
             let messages = transport.drain_messages_to_send(|_| sim_time.should_send_message_now());
             for message in messages {
                 match message.delivery {
                     DeliveryRequirement::Unreliable | DeliveryRequirement::Default => {
-                        if let Err(e) = socket.send_to(&message.payload, message.destination) {
+                        if let Err(e) = socket.send_to(&message.wire_payload(), message.destination)
+                        {
+                            drops.record(DropReason::SendFailed);
                             channel.single_write(NetworkSimulationEvent::SendError(e, message));
                         }
                     }
@@ -106,20 +122,29 @@ impl UdpNetworkRecvSystem {
 impl<'s> System<'s> for UdpNetworkRecvSystem {
     type SystemData = (
         Write<'s, UdpSocketResource>,
+        Write<'s, FragmentReassembler>,
+        Write<'s, ReceiveGate>,
         Write<'s, EventChannel<NetworkSimulationEvent>>,
     );
 
-    fn run(&mut self, (mut socket, mut event_channel): Self::SystemData) {
+    fn run(
+        &mut self,
+        (mut socket, mut reassembler, mut gate, mut event_channel): Self::SystemData,
+    ) {
+        reassembler.sweep(Instant::now(), &mut event_channel);
+        gate.release(&mut event_channel);
         if let Some(socket) = socket.get_mut() {
             loop {
                 match socket.recv_from(&mut self.recv_buffer) {
                     Ok((recv_len, address)) => {
-                        let event = NetworkSimulationEvent::Message(
+                        // TODO: Handle other types of events.
+                        wire::receive(
                             address,
                             Bytes::copy_from_slice(&self.recv_buffer[..recv_len]),
+                            &mut reassembler,
+                            &mut gate,
+                            &mut event_channel,
                         );
-                        // TODO: Handle other types of events.
-                        event_channel.single_write(event);
                     }
                     Err(e) => {
                         if e.kind() != io::ErrorKind::WouldBlock {
@@ -170,3 +195,250 @@ impl UdpSocketResource {
         self.socket = None;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{
+        requirements::UrgencyRequirement, transport::test_utils::measure_latency,
+    };
+    use amethyst_core::ecs::{RunNow, WorldExt};
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn test_held_messages_are_delivered_in_order_on_release() {
+        let receiver_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver_socket.set_nonblocking(true).unwrap();
+        let receiver_addr = receiver_socket.local_addr().unwrap();
+        let mut world = World::new();
+        world.insert(UdpSocketResource::new(Some(receiver_socket)));
+        world.insert(EventChannel::<NetworkSimulationEvent>::new());
+        world.insert(ReceiveGate::new());
+        world.insert(FragmentReassembler::default());
+        let mut reader = world
+            .fetch_mut::<EventChannel<NetworkSimulationEvent>>()
+            .register_reader();
+        let mut recv_system = UdpNetworkRecvSystem::with_buffer_capacity(1500);
+
+        let read_messages = |reader: &mut _| {
+            world
+                .fetch::<EventChannel<NetworkSimulationEvent>>()
+                .read(reader)
+                .filter_map(|event| match event {
+                    NetworkSimulationEvent::Message(_, payload, _) => Some(payload.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Simulate a level load
+        world.fetch_mut::<ReceiveGate>().hold = true;
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        for payload in [b"one", b"two", b"six"].iter() {
+            sender
+                .send_to(&wire::encode_message(&payload[..]), receiver_addr)
+                .unwrap();
+        }
+        for _ in 0..100 {
+            recv_system.run_now(&world);
+            if world.fetch::<ReceiveGate>().held_messages() == 3 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(world.fetch::<ReceiveGate>().held_messages(), 3);
+        assert!(read_messages(&mut reader).is_empty());
+
+        world.fetch_mut::<ReceiveGate>().hold = false;
+        recv_system.run_now(&world);
+        assert_eq!(world.fetch::<ReceiveGate>().held_messages(), 0);
+        assert_eq!(
+            read_messages(&mut reader),
+            vec![&b"one"[..], &b"two"[..], &b"six"[..]]
+        );
+    }
+
+    #[test]
+    fn test_partial_message_is_discarded_after_reassembly_timeout() {
+        let receiver_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver_socket.set_nonblocking(true).unwrap();
+        let receiver_addr = receiver_socket.local_addr().unwrap();
+        let mut world = World::new();
+        world.insert(UdpSocketResource::new(Some(receiver_socket)));
+        world.insert(EventChannel::<NetworkSimulationEvent>::new());
+        world.insert(ReceiveGate::new());
+        world.insert(FragmentReassembler::new(Duration::from_millis(20)));
+        let mut reader = world
+            .fetch_mut::<EventChannel<NetworkSimulationEvent>>()
+            .register_reader();
+        let mut recv_system = UdpNetworkRecvSystem::with_buffer_capacity(1500);
+
+        let mut transport = TransportResource::new();
+        transport
+            .send_fragmented(
+                receiver_addr,
+                &[42; 64],
+                16,
+                DeliveryRequirement::Unreliable,
+                UrgencyRequirement::Immediate,
+            )
+            .unwrap();
+        let mut fragments = transport.drain_messages(|_| true);
+        assert_eq!(fragments.len(), 4);
+        fragments.pop();
+
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sender_addr = sender.local_addr().unwrap();
+        for fragment in fragments {
+            sender
+                .send_to(&fragment.wire_payload(), receiver_addr)
+                .unwrap();
+        }
+        for _ in 0..100 {
+            recv_system.run_now(&world);
+            thread::sleep(Duration::from_millis(1));
+            let reassembler = world.fetch::<FragmentReassembler>();
+            if reassembler.partial_messages() == 1 {
+                break;
+            }
+        }
+        assert_eq!(world.fetch::<FragmentReassembler>().partial_messages(), 1);
+
+        thread::sleep(Duration::from_millis(30));
+        recv_system.run_now(&world);
+        assert_eq!(world.fetch::<FragmentReassembler>().partial_messages(), 0);
+        let events = world
+            .fetch::<EventChannel<NetworkSimulationEvent>>()
+            .read(&mut reader)
+            .map(|event| format!("{:?}", event))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![format!(
+                "{:?}",
+                NetworkSimulationEvent::ReassemblyTimeout {
+                    addr: sender_addr,
+                    msg_id: 0
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_response_carries_correlation_id_of_request() {
+        let bind = || {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            socket.set_nonblocking(true).unwrap();
+            let addr = socket.local_addr().unwrap();
+            let mut world = World::new();
+            world.insert(UdpSocketResource::new(Some(socket)));
+            world.insert(EventChannel::<NetworkSimulationEvent>::new());
+            world.insert(ReceiveGate::new());
+            world.insert(FragmentReassembler::default());
+            world.insert(NetworkSimulationTime::default());
+            world.insert(DropStats::default());
+            world.insert(TransportResource::new());
+            let reader = world
+                .fetch_mut::<EventChannel<NetworkSimulationEvent>>()
+                .register_reader();
+            (world, addr, reader)
+        };
+        let receive = |world: &World, reader: &mut _| {
+            let mut recv_system = UdpNetworkRecvSystem::with_buffer_capacity(1500);
+            for _ in 0..100 {
+                recv_system.run_now(world);
+                let received = world
+                    .fetch::<EventChannel<NetworkSimulationEvent>>()
+                    .read(reader)
+                    .find_map(|event| match event {
+                        NetworkSimulationEvent::Message(addr, payload, Some(correlation_id)) => {
+                            Some((*addr, *correlation_id, payload.clone()))
+                        }
+                        _ => None,
+                    });
+                if received.is_some() {
+                    return received;
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+            None
+        };
+        let (client, client_addr, mut client_reader) = bind();
+        let (server, server_addr, mut server_reader) = bind();
+
+        let mut request_ids = Vec::new();
+        for payload in [&b"ping"[..], &b"pong"[..]].iter() {
+            request_ids.push(
+                client
+                    .fetch_mut::<TransportResource>()
+                    .request(server_addr, payload, DeliveryRequirement::Unreliable)
+                    .unwrap(),
+            );
+        }
+        assert_ne!(request_ids[0], request_ids[1]);
+        UdpNetworkSendSystem.run_now(&client);
+
+        let (addr, correlation_id, payload) = receive(&server, &mut server_reader).unwrap();
+        assert_eq!(addr, client_addr);
+        assert_eq!(correlation_id, request_ids[0]);
+        assert_eq!(payload, &b"ping"[..]);
+
+        server
+            .fetch_mut::<TransportResource>()
+            .respond(
+                client_addr,
+                correlation_id,
+                b"response",
+                DeliveryRequirement::Unreliable,
+            )
+            .unwrap();
+        UdpNetworkSendSystem.run_now(&server);
+        let (addr, correlation_id, payload) = receive(&client, &mut client_reader).unwrap();
+        assert_eq!(addr, server_addr);
+        assert_eq!(correlation_id, request_ids[0]);
+        assert_eq!(payload, &b"response"[..]);
+    }
+
+    #[test]
+    fn test_measured_latency_matches_simulated_latency() {
+        let simulated_latency = Duration::from_millis(50);
+
+        let receiver_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver_socket.set_nonblocking(true).unwrap();
+        let receiver_addr = receiver_socket.local_addr().unwrap();
+        let mut receiver = World::new();
+        receiver.insert(UdpSocketResource::new(Some(receiver_socket)));
+        receiver.insert(EventChannel::<NetworkSimulationEvent>::new());
+        receiver.insert(ReceiveGate::new());
+        receiver.insert(FragmentReassembler::default());
+        let mut reader = receiver
+            .fetch_mut::<EventChannel<NetworkSimulationEvent>>()
+            .register_reader();
+
+        let sender_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender_socket.set_nonblocking(true).unwrap();
+        let mut sender = World::new();
+        sender.insert(UdpSocketResource::new(Some(sender_socket)));
+        sender.insert(EventChannel::<NetworkSimulationEvent>::new());
+        sender.insert(NetworkSimulationTime::default());
+        sender.insert(DropStats::default());
+        let mut transport = TransportResource::new();
+        transport.set_simulated_latency(Some(simulated_latency));
+        sender.insert(transport);
+
+        let latency = measure_latency(
+            &sender,
+            &mut UdpNetworkSendSystem,
+            &receiver,
+            &mut UdpNetworkRecvSystem::with_buffer_capacity(1500),
+            &mut reader,
+            receiver_addr,
+            b"ping",
+            Duration::from_secs(1),
+        )
+        .expect("Message was not received");
+
+        assert!(latency >= simulated_latency);
+        assert!(latency < simulated_latency + Duration::from_millis(25));
+    }
+}
@@ -0,0 +1,282 @@
+//! Header the transports prefix to every message they send, telling the receiving end how to
+//! handle the message before its payload is delivered to the application.
+//!
+//! The header starts with a byte of flags. Control messages have `CONTROL_FLAG` set and their
+//! body is a `Control` instead of an application payload, so nothing an application sends can be
+//! mistaken for one. The other flags announce optional fields, which follow the flags in the order
+//! of their flag.
+
+use crate::simulation::{
+    events::NetworkSimulationEvent,
+    fragmentation::{Fragment, FragmentReassembler},
+    gate::ReceiveGate,
+};
+use amethyst_core::shrev::EventChannel;
+use bytes::Bytes;
+use std::{convert::TryInto, io, net::SocketAddr, time::Instant};
+
+/// Set on control messages.
+const CONTROL_FLAG: u8 = 0b0000_0001;
+/// Set when the header carries a correlation id, as a big endian `u64`.
+const CORRELATION_FLAG: u8 = 0b0000_0010;
+/// Set when the message is a fragment, followed by the message id as a big endian `u32`, then the
+/// index of the fragment and the fragment count as big endian `u16`s.
+const FRAGMENT_FLAG: u8 = 0b0000_0100;
+/// Every flag this version of the header knows about.
+const KNOWN_FLAGS: u8 = CONTROL_FLAG | CORRELATION_FLAG | FRAGMENT_FLAG;
+
+/// Kind byte of a `Control::Kick`.
+const KICK_KIND: u8 = 0;
+/// Kind byte of a `Control::SessionToken`.
+const SESSION_TOKEN_KIND: u8 = 1;
+
+/// Control messages exchanged by the transports on the reserved `CONTROL_CHANNEL`. They are
+/// handled by the receive systems and never delivered as a `NetworkSimulationEvent::Message`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Control {
+    /// The sender kicked the receiver for the given reason.
+    Kick { reason: String },
+    /// The sender presents the token of its session, see
+    /// `LaminarSocketResource::present_session_token`.
+    SessionToken(u64),
+}
+
+/// Optional fields of the header of an application message.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Header {
+    /// The id matching a response to its request, see `TransportResource::request`.
+    pub correlation_id: Option<u64>,
+    /// The position of the message in a fragmented message.
+    pub fragment: Option<Fragment>,
+}
+
+impl Header {
+    /// Returns whether the header carries any field, which a transport without header can't send.
+    pub(crate) fn is_empty(&self) -> bool {
+        *self == Header::default()
+    }
+}
+
+/// A message decoded from the wire.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Received {
+    /// An application payload.
+    Message(Header, Bytes),
+    /// A control message for the transport.
+    Control(Control),
+}
+
+/// Returns an empty header followed by the application payload.
+#[cfg(test)]
+pub(crate) fn encode_message(payload: &[u8]) -> Vec<u8> {
+    encode(&Header::default(), payload)
+}
+
+/// Returns the header followed by the application payload.
+pub(crate) fn encode(header: &Header, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(17 + payload.len());
+    bytes.push(0);
+    if let Some(correlation_id) = header.correlation_id {
+        bytes[0] |= CORRELATION_FLAG;
+        bytes.extend_from_slice(&correlation_id.to_be_bytes());
+    }
+    if let Some(fragment) = header.fragment {
+        bytes[0] |= FRAGMENT_FLAG;
+        bytes.extend_from_slice(&fragment.msg_id.to_be_bytes());
+        bytes.extend_from_slice(&fragment.index.to_be_bytes());
+        bytes.extend_from_slice(&fragment.count.to_be_bytes());
+    }
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+/// Returns the header followed by the control message.
+pub(crate) fn encode_control(control: &Control) -> Vec<u8> {
+    let mut bytes = vec![CONTROL_FLAG];
+    match control {
+        Control::Kick { reason } => {
+            bytes.push(KICK_KIND);
+            bytes.extend_from_slice(reason.as_bytes());
+        }
+        Control::SessionToken(token) => {
+            bytes.push(SESSION_TOKEN_KIND);
+            bytes.extend_from_slice(&token.to_be_bytes());
+        }
+    }
+    bytes
+}
+
+/// Decodes a message received from the wire.
+pub(crate) fn decode(bytes: Bytes) -> io::Result<Received> {
+    let flags = match bytes.first() {
+        Some(&flags) => flags,
+        None => return Err(invalid_data("Received a message without header")),
+    };
+    if flags & !KNOWN_FLAGS != 0 {
+        return Err(invalid_data(format!(
+            "Received a message with unknown header flags {:#010b}",
+            flags
+        )));
+    }
+    if flags & CONTROL_FLAG == 0 {
+        let mut header = Header::default();
+        let mut offset = 1;
+        if flags & CORRELATION_FLAG != 0 {
+            let field = bytes.get(offset..offset + 8).ok_or_else(|| {
+                invalid_data("Received a message with a truncated correlation id")
+            })?;
+            header.correlation_id = Some(u64::from_be_bytes(
+                field
+                    .try_into()
+                    .expect("Unreachable: the field has the size of an u64"),
+            ));
+            offset += 8;
+        }
+        if flags & FRAGMENT_FLAG != 0 {
+            let field = bytes
+                .get(offset..offset + 8)
+                .ok_or_else(|| invalid_data("Received a message with a truncated fragment"))?;
+            header.fragment = Some(Fragment {
+                msg_id: u32::from_be_bytes([field[0], field[1], field[2], field[3]]),
+                index: u16::from_be_bytes([field[4], field[5]]),
+                count: u16::from_be_bytes([field[6], field[7]]),
+            });
+            offset += 8;
+        }
+        return Ok(Received::Message(header, bytes.slice(offset..)));
+    }
+    match bytes.get(1) {
+        Some(&KICK_KIND) => Ok(Received::Control(Control::Kick {
+            reason: String::from_utf8_lossy(&bytes[2..]).into_owned(),
+        })),
+        Some(&SESSION_TOKEN_KIND) => match bytes[2..].try_into() {
+            Ok(token) => Ok(Received::Control(Control::SessionToken(
+                u64::from_be_bytes(token),
+            ))),
+            Err(_) => Err(invalid_data("Received a malformed session token")),
+        },
+        Some(kind) => Err(invalid_data(format!(
+            "Received an unknown control message of kind {}",
+            kind
+        ))),
+        None => Err(invalid_data("Received a control message without kind")),
+    }
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Delivers a received message: the application payloads are emitted as messages once reassembled,
+/// kicks are emitted as `NetworkSimulationEvent::Kicked` and malformed messages are reported with a
+/// `NetworkSimulationEvent::RecvError`.
+pub(crate) fn receive(
+    addr: SocketAddr,
+    bytes: Bytes,
+    reassembler: &mut FragmentReassembler,
+    gate: &mut ReceiveGate,
+    channel: &mut EventChannel<NetworkSimulationEvent>,
+) {
+    match decode(bytes) {
+        Ok(Received::Message(header, payload)) => {
+            deliver(addr, header, payload, reassembler, gate, channel)
+        }
+        Ok(Received::Control(control)) => handle_control(addr, control, channel),
+        Err(e) => channel.single_write(NetworkSimulationEvent::RecvError(e)),
+    }
+}
+
+/// Emits the received payload as a message once reassembled.
+pub(crate) fn deliver(
+    addr: SocketAddr,
+    header: Header,
+    payload: Bytes,
+    reassembler: &mut FragmentReassembler,
+    gate: &mut ReceiveGate,
+    channel: &mut EventChannel<NetworkSimulationEvent>,
+) {
+    if let Some(payload) = reassembler.reassemble(addr, header.fragment, payload, Instant::now()) {
+        gate.write_message(addr, payload, header.correlation_id, channel);
+    }
+}
+
+/// Emits the events of a received control message. Session tokens are only meaningful to the
+/// laminar transport, which handles them itself, and are ignored here.
+pub(crate) fn handle_control(
+    addr: SocketAddr,
+    control: Control,
+    channel: &mut EventChannel<NetworkSimulationEvent>,
+) {
+    match control {
+        Control::Kick { reason } => {
+            channel.single_write(NetworkSimulationEvent::Kicked { addr, reason })
+        }
+        Control::SessionToken(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_control_messages_are_told_apart_from_payloads() {
+        let kick = Control::Kick {
+            reason: "cheating".to_owned(),
+        };
+        assert_eq!(
+            decode(Bytes::from(encode_control(&kick))).unwrap(),
+            Received::Control(kick)
+        );
+
+        // A payload looking like a control message is still a payload
+        let payload = encode_control(&Control::Kick {
+            reason: "nope".to_owned(),
+        });
+        assert_eq!(
+            decode(Bytes::from(encode_message(&payload))).unwrap(),
+            Received::Message(Header::default(), Bytes::from(payload))
+        );
+
+        assert!(decode(Bytes::new()).is_err());
+        assert!(decode(Bytes::from_static(&[0b1000_0000])).is_err());
+        assert!(decode(Bytes::from_static(&[CONTROL_FLAG, 42])).is_err());
+
+        let token = Control::SessionToken(42);
+        assert_eq!(
+            decode(Bytes::from(encode_control(&token))).unwrap(),
+            Received::Control(token)
+        );
+        assert!(decode(Bytes::from_static(&[CONTROL_FLAG, SESSION_TOKEN_KIND, 0])).is_err());
+    }
+
+    #[test]
+    fn test_correlation_id_is_carried_in_header() {
+        let header = Header {
+            correlation_id: Some(7),
+            fragment: None,
+        };
+        assert_eq!(
+            decode(Bytes::from(encode(&header, b"ping"))).unwrap(),
+            Received::Message(header, Bytes::from_static(b"ping"))
+        );
+        assert!(decode(Bytes::from_static(&[CORRELATION_FLAG, 0, 0])).is_err());
+    }
+
+    #[test]
+    fn test_fragment_is_carried_in_header() {
+        let header = Header {
+            correlation_id: Some(7),
+            fragment: Some(Fragment {
+                msg_id: 3,
+                index: 1,
+                count: 2,
+            }),
+        };
+        assert_eq!(
+            decode(Bytes::from(encode(&header, b"ping"))).unwrap(),
+            Received::Message(header, Bytes::from_static(b"ping"))
+        );
+        assert!(decode(Bytes::from_static(&[FRAGMENT_FLAG, 0, 0, 0])).is_err());
+    }
+}
@@ -111,7 +111,7 @@ impl<'a> System<'a> for SpamSystem {
 
         for event in event.read(&mut self.reader) {
             match event {
-                NetworkSimulationEvent::Message(_addr, payload) => info!("Payload: {:?}", payload),
+                NetworkSimulationEvent::Message(_addr, payload, _) => info!("Payload: {:?}", payload),
                 NetworkSimulationEvent::Connect(addr) => info!("New client connection: {}", addr),
                 NetworkSimulationEvent::Disconnect(addr) => info!("Server Disconnected: {}", addr),
                 NetworkSimulationEvent::RecvError(e) => {
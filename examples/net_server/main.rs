@@ -104,7 +104,7 @@ impl<'a> System<'a> for SpamReceiveSystem {
     fn run(&mut self, (mut net, channel): Self::SystemData) {
         for event in channel.read(&mut self.reader) {
             match event {
-                NetworkSimulationEvent::Message(addr, payload) => {
+                NetworkSimulationEvent::Message(addr, payload, _) => {
                     info!("{}: {:?}", addr, payload);
                     // In a typical client/server simulation, both the client and the server will
                     // be exchanging messages at a constant rate. Laminar makes use of this by